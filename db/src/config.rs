@@ -1,5 +1,54 @@
 use std::path::PathBuf;
 
+use crate::{Compression, Db};
+
 pub struct Config {
     pub path: PathBuf,
+    /// The memtable is flushed to a table once the keys and values that
+    /// it holds exceed this many bytes.
+    pub flush_threshold_bytes: usize,
+    /// A run of similarly sized tables is compacted into one once it holds
+    /// at least this many tables, and at most twice this many are merged
+    /// at once.
+    pub compaction_trigger_tables: usize,
+    /// Limits the rate at which compaction reads tables, or `None` to
+    /// compact as fast as possible. Can be changed while the database is
+    /// open with [`Db::set_compaction_rate_limit`].
+    pub compaction_bytes_per_second: Option<u64>,
+    /// The memory budget for caching table data blocks. Blocks that are
+    /// read repeatedly are kept in preference to those read once, such as
+    /// by a range scan.
+    pub block_cache_bytes: usize,
+    /// How log frames and table blocks are compressed. Changing it only
+    /// affects files written from then on.
+    pub compression: Compression,
+    /// The keys that logs, tables and objects are encrypted with, or
+    /// `None` to store them unencrypted. Changing the current key only
+    /// affects files written from then on, so previous keys must be kept
+    /// in the keyring while files encrypted with them remain. A database
+    /// created without encryption can't be opened with a keyring, as its
+    /// unencrypted files are refused.
+    #[cfg(feature = "encryption")]
+    pub encryption: Option<crate::Keyring>,
+}
+
+impl Config {
+    /// The default configuration for a database stored at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Config {
+        Config {
+            path: path.into(),
+            flush_threshold_bytes: 64 * 1024 * 1024,
+            compaction_trigger_tables: 4,
+            compaction_bytes_per_second: None,
+            block_cache_bytes: 64 * 1024 * 1024,
+            compression: Compression::None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+        }
+    }
+
+    /// Open the database with this configuration.
+    pub fn open(self) -> std::io::Result<Db> {
+        crate::db::open_with_config(self)
+    }
 }
@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::util::BytesBlock;
+
+/// Counters describing the effectiveness of the block cache since the
+/// database was opened.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads of a data block that were served from memory.
+    pub hits: u64,
+    /// Reads of a data block that went to the file system.
+    pub misses: u64,
+    /// Blocks removed from the cache to stay within its budget.
+    pub evictions: u64,
+    /// The bytes of blocks currently held by the cache.
+    pub bytes: u64,
+}
+
+/// Identifies a data block by the id of its table and its position
+/// within that table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct BlockId {
+    pub(crate) table_id: u64,
+    pub(crate) block_index: usize,
+}
+
+/// A cache of decoded table data blocks, bounded by a byte budget.
+///
+/// Uses the W-TinyLFU policy to resist scans: new blocks enter a small
+/// LRU window, and a block leaving the window only displaces a block in
+/// the main cache if it has been read more often recently, as estimated
+/// by a count-min sketch. A long scan therefore churns the window rather
+/// than flushing frequently read blocks out of the main cache. The main
+/// cache is a segmented LRU, in which blocks read again while on
+/// probation are promoted to a protected segment.
+pub(crate) struct BlockCache {
+    state: Mutex<State>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Window,
+    Probation,
+    Protected,
+}
+
+struct Entry {
+    block: Arc<BytesBlock>,
+    size: usize,
+    segment: Segment,
+    tick: u64,
+}
+
+/// An LRU ordering of the blocks in one segment, oldest first.
+#[derive(Default)]
+struct Lru {
+    order: BTreeMap<u64, BlockId>,
+    bytes: usize,
+}
+
+impl Lru {
+    fn oldest(&self) -> Option<BlockId> {
+        self.order.values().next().copied()
+    }
+}
+
+struct State {
+    entries: HashMap<BlockId, Entry>,
+    window: Lru,
+    probation: Lru,
+    protected: Lru,
+    window_budget: usize,
+    main_budget: usize,
+    protected_budget: usize,
+    next_tick: u64,
+    sketch: FrequencySketch,
+}
+
+impl BlockCache {
+    pub(crate) fn new(budget_bytes: usize) -> BlockCache {
+        // 1% window and 80% of the main cache protected, as recommended
+        // for W-TinyLFU
+        let window_budget = budget_bytes / 100;
+        let main_budget = budget_bytes - window_budget;
+
+        BlockCache {
+            state: Mutex::new(State {
+                entries: HashMap::new(),
+                window: Lru::default(),
+                probation: Lru::default(),
+                protected: Lru::default(),
+                window_budget,
+                main_budget,
+                protected_budget: main_budget / 5 * 4,
+                // zero marks an entry that is not in any segment yet
+                next_tick: 1,
+                sketch: FrequencySketch::new(budget_bytes),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached block, or reads it with `read` and caches it.
+    pub(crate) fn get_or_read<E>(
+        &self,
+        id: BlockId,
+        read: impl FnOnce() -> Result<BytesBlock, E>,
+    ) -> Result<Arc<BytesBlock>, E> {
+        if let Some(block) = self.state.lock().unwrap().get(id) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(block);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        // read without holding the lock, racing readers of the same block
+        // both insert it and the later one wins
+        let block = Arc::new(read()?);
+        let size = block.as_bytes().len();
+
+        let evicted = self.state.lock().unwrap().insert(id, block.clone(), size);
+        self.evictions.fetch_add(evicted, Ordering::Relaxed);
+
+        Ok(block)
+    }
+
+    /// Forgets the blocks of a table whose file was deleted.
+    pub(crate) fn remove_table(&self, table_id: u64) {
+        let mut state = self.state.lock().unwrap();
+
+        let ids: Vec<BlockId> = state
+            .entries
+            .keys()
+            .filter(|id| id.table_id == table_id)
+            .copied()
+            .collect();
+
+        for id in ids {
+            state.remove(id);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            bytes: (state.window.bytes + state.probation.bytes + state.protected.bytes) as u64,
+        }
+    }
+}
+
+impl State {
+    fn lru(&mut self, segment: Segment) -> &mut Lru {
+        match segment {
+            Segment::Window => &mut self.window,
+            Segment::Probation => &mut self.probation,
+            Segment::Protected => &mut self.protected,
+        }
+    }
+
+    /// Moves a block to the most recently used end of `segment`.
+    fn place(&mut self, id: BlockId, segment: Segment) {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+
+        let entry = self.entries.get_mut(&id).unwrap();
+        let (old_segment, old_tick, size) = (entry.segment, entry.tick, entry.size);
+        entry.segment = segment;
+        entry.tick = tick;
+
+        let old_lru = self.lru(old_segment);
+        if old_lru.order.remove(&old_tick).is_some() {
+            old_lru.bytes -= size;
+        }
+
+        let lru = self.lru(segment);
+        lru.order.insert(tick, id);
+        lru.bytes += size;
+    }
+
+    fn remove(&mut self, id: BlockId) {
+        if let Some(entry) = self.entries.remove(&id) {
+            let lru = self.lru(entry.segment);
+            lru.order.remove(&entry.tick);
+            lru.bytes -= entry.size;
+        }
+    }
+
+    fn get(&mut self, id: BlockId) -> Option<Arc<BytesBlock>> {
+        self.sketch.increment(&id);
+
+        let entry = self.entries.get(&id)?;
+        let block = entry.block.clone();
+
+        match entry.segment {
+            Segment::Window => self.place(id, Segment::Window),
+            Segment::Probation | Segment::Protected => {
+                self.place(id, Segment::Protected);
+
+                // demote the least recently used protected blocks
+                while self.protected.bytes > self.protected_budget {
+                    let demoted = self.protected.oldest().unwrap();
+                    self.place(demoted, Segment::Probation);
+                }
+            }
+        }
+
+        Some(block)
+    }
+
+    /// Inserts a block into the window, returning the number of blocks
+    /// that were evicted to stay within the budget.
+    fn insert(&mut self, id: BlockId, block: Arc<BytesBlock>, size: usize) -> u64 {
+        self.remove(id);
+
+        self.entries.insert(
+            id,
+            Entry {
+                block,
+                size,
+                segment: Segment::Window,
+                tick: 0,
+            },
+        );
+        self.place(id, Segment::Window);
+
+        let mut evicted = 0;
+
+        // Blocks leaving the window compete with the oldest probationary
+        // block for admission to the main cache
+        while self.window.bytes > self.window_budget {
+            let candidate = self.window.oldest().unwrap();
+            self.place(candidate, Segment::Probation);
+
+            while self.probation.bytes + self.protected.bytes > self.main_budget {
+                let victim = self.probation.oldest().unwrap();
+
+                let evict = if victim == candidate {
+                    candidate
+                } else if self.sketch.frequency(&candidate) > self.sketch.frequency(&victim) {
+                    victim
+                } else {
+                    candidate
+                };
+
+                self.remove(evict);
+                evicted += 1;
+
+                if evict == candidate {
+                    break;
+                }
+            }
+        }
+
+        evicted
+    }
+}
+
+/// Estimates how often each block was read recently, using a count-min
+/// sketch of small saturating counters that are halved periodically so
+/// that old popularity fades.
+struct FrequencySketch {
+    counters: Vec<[u8; ROWS]>,
+    hasher: RandomState,
+    additions: usize,
+    reset_after: usize,
+}
+
+const ROWS: usize = 4;
+const MAX_COUNT: u8 = 15;
+
+/// Blocks are tens of KiB, so a sketch sized for this many blocks per
+/// byte of budget comfortably covers the blocks that fit.
+const ASSUMED_BLOCK_BYTES: usize = 4096;
+
+impl FrequencySketch {
+    fn new(budget_bytes: usize) -> FrequencySketch {
+        let width = (budget_bytes / ASSUMED_BLOCK_BYTES)
+            .max(16)
+            .next_power_of_two();
+
+        FrequencySketch {
+            counters: vec![[0; ROWS]; width],
+            hasher: RandomState::new(),
+            additions: 0,
+            reset_after: width * 10,
+        }
+    }
+
+    fn slots<T: Hash>(&self, item: &T) -> [usize; ROWS] {
+        let hash = self.hasher.hash_one(item);
+        let mask = self.counters.len() - 1;
+
+        // derive a differently-mixed index for each row from one hash
+        std::array::from_fn(|row| {
+            let mixed = hash.rotate_left(row as u32 * 16) ^ (hash >> (row * 7));
+            mixed as usize & mask
+        })
+    }
+
+    fn frequency<T: Hash>(&self, item: &T) -> u8 {
+        let slots = self.slots(item);
+        (0..ROWS)
+            .map(|row| self.counters[slots[row]][row])
+            .min()
+            .unwrap()
+    }
+
+    fn increment<T: Hash>(&mut self, item: &T) {
+        let slots = self.slots(item);
+        for (row, slot) in slots.into_iter().enumerate() {
+            let counter = &mut self.counters[slot][row];
+            *counter = (*counter + 1).min(MAX_COUNT);
+        }
+
+        self.additions += 1;
+        if self.additions >= self.reset_after {
+            for counters in &mut self.counters {
+                for counter in counters {
+                    *counter /= 2;
+                }
+            }
+            self.additions = 0;
+        }
+    }
+}
+
+#[test]
+fn smoke_block_cache() {
+    use std::collections::BTreeMap as Map;
+
+    let block = |byte: u8| {
+        let mut map = Map::new();
+        map.insert(vec![byte], vec![byte; 1000]);
+        BytesBlock::from(&map)
+    };
+    let size = block(0).as_bytes().len();
+    let id = |block_index| BlockId {
+        table_id: 1,
+        block_index,
+    };
+
+    let cache = BlockCache::new(size * 100);
+
+    // a hot set of blocks that is read repeatedly
+    for _ in 0..5 {
+        for i in 0..20 {
+            cache
+                .get_or_read(id(i), || Ok::<_, ()>(block(i as u8)))
+                .unwrap();
+        }
+    }
+
+    let after_warmup = cache.stats();
+    assert_eq!(after_warmup.misses, 20);
+    assert_eq!(after_warmup.hits, 80);
+
+    // a scan over many more blocks than fit, each read once
+    for i in 1000..3000 {
+        cache.get_or_read(id(i), || Ok::<_, ()>(block(0))).unwrap();
+    }
+
+    let stats = cache.stats();
+    assert!(stats.bytes <= (size * 100) as u64);
+    assert!(stats.evictions > 0);
+
+    // the hot set survived the scan, except for the most recently read
+    // block, which was still in the window rather than protected
+    for i in 0..19 {
+        cache
+            .get_or_read(id(i), || Err(format!("block {i} was evicted")))
+            .unwrap();
+    }
+
+    cache.remove_table(1);
+    assert_eq!(cache.stats().bytes, 0);
+}
@@ -0,0 +1,153 @@
+use std::ops::Bound;
+
+use super::range::prefix_successor;
+use crate::{Bytes, CollectionId};
+
+/// A handle to a named collection, returned by
+/// [`Db::open_collection`](super::Db::open_collection).
+///
+/// Each collection is an independent keyspace. A single [`Tx`](super::Tx)
+/// may read and write any number of collections and commits all of them
+/// atomically. Handles remain valid across renames, but writes through a
+/// handle to a dropped collection fail at commit time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Collection {
+    pub(crate) id: CollectionId,
+}
+
+impl Collection {
+    pub(crate) const CATALOG: Collection = Collection {
+        id: CollectionId::CATALOG,
+    };
+
+    pub(crate) const DEFAULT: Collection = Collection {
+        id: CollectionId::DEFAULT,
+    };
+
+    pub(crate) const OBJECTS: Collection = Collection {
+        id: CollectionId::OBJECTS,
+    };
+}
+
+// Keys in the catalog collection. Names map to ids and ids map back to
+// names, and a counter ensures that ids of dropped collections are never
+// reused. Logs have their own namespace of names, and a counter of the
+// next sequence number to assign to each log. Transactor names map to
+// the position in which they were first registered.
+const NAME_TAG: u8 = 0;
+const ID_TAG: u8 = 1;
+const NEXT_ID_TAG: u8 = 2;
+const LOG_NAME_TAG: u8 = 3;
+const LOG_TAIL_TAG: u8 = 4;
+const TRANSACTOR_TAG: u8 = 5;
+const NEXT_TRANSACTOR_POSITION_TAG: u8 = 6;
+
+pub(crate) fn catalog_name_key(name: &str) -> Bytes {
+    let mut key = Vec::with_capacity(1 + name.len());
+    key.push(NAME_TAG);
+    key.extend_from_slice(name.as_bytes());
+    Bytes::from(key)
+}
+
+pub(crate) fn catalog_log_name_key(name: &str) -> Bytes {
+    let mut key = Vec::with_capacity(1 + name.len());
+    key.push(LOG_NAME_TAG);
+    key.extend_from_slice(name.as_bytes());
+    Bytes::from(key)
+}
+
+pub(crate) fn catalog_transactor_key(name: &str) -> Bytes {
+    let mut key = Vec::with_capacity(1 + name.len());
+    key.push(TRANSACTOR_TAG);
+    key.extend_from_slice(name.as_bytes());
+    Bytes::from(key)
+}
+
+pub(crate) fn catalog_id_key(id: CollectionId) -> Bytes {
+    let mut key = Vec::with_capacity(9);
+    key.push(ID_TAG);
+    key.extend_from_slice(&id.to_be_bytes());
+    Bytes::from(key)
+}
+
+pub(crate) fn catalog_log_tail_key(id: CollectionId) -> Bytes {
+    let mut key = Vec::with_capacity(9);
+    key.push(LOG_TAIL_TAG);
+    key.extend_from_slice(&id.to_be_bytes());
+    Bytes::from(key)
+}
+
+pub(crate) fn catalog_next_id_key() -> Bytes {
+    Bytes::from(vec![NEXT_ID_TAG])
+}
+
+pub(crate) fn catalog_next_transactor_position_key() -> Bytes {
+    Bytes::from(vec![NEXT_TRANSACTOR_POSITION_TAG])
+}
+
+pub(crate) fn catalog_name_prefix() -> [u8; 1] {
+    [NAME_TAG]
+}
+
+pub(crate) fn decode_id(value: &[u8]) -> CollectionId {
+    let bytes: [u8; 8] = value.try_into().expect("corrupt collection id in catalog");
+    CollectionId::from_be_bytes(bytes).expect("corrupt collection id in catalog")
+}
+
+/// Storage keys are prefixed with the big-endian id of their collection,
+/// so each collection occupies a contiguous, ordered region of storage.
+pub(crate) fn encode_key(id: CollectionId, key: &[u8]) -> Bytes {
+    let mut encoded = Vec::with_capacity(8 + key.len());
+    encoded.extend_from_slice(&id.to_be_bytes());
+    encoded.extend_from_slice(key);
+    Bytes::from(encoded)
+}
+
+pub(crate) fn decode_key(encoded: &[u8]) -> (CollectionId, Bytes) {
+    let (id_bytes, key) = encoded.split_at(8);
+    let id = CollectionId::from_be_bytes(id_bytes.try_into().unwrap())
+        .expect("storage key has an invalid collection id");
+    (id, Bytes::from(key))
+}
+
+/// Translates bounds on keys within a collection into bounds on storage
+/// keys that cover only that collection.
+pub(crate) fn encode_bounds(
+    id: CollectionId,
+    lo: Bound<&Bytes>,
+    hi: Bound<&Bytes>,
+) -> (Bound<Bytes>, Bound<Bytes>) {
+    let lo = match lo {
+        Bound::Included(k) => Bound::Included(encode_key(id, k)),
+        Bound::Excluded(k) => Bound::Excluded(encode_key(id, k)),
+        Bound::Unbounded => Bound::Included(encode_key(id, &[])),
+    };
+
+    let hi = match hi {
+        Bound::Included(k) => Bound::Included(encode_key(id, k)),
+        Bound::Excluded(k) => Bound::Excluded(encode_key(id, k)),
+        Bound::Unbounded => match prefix_successor(&id.to_be_bytes()) {
+            Some(successor) => Bound::Excluded(successor),
+            None => Bound::Unbounded,
+        },
+    };
+
+    (lo, hi)
+}
+
+#[test]
+fn smoke_key_encoding() {
+    let id = CollectionId::FIRST_NAMED;
+    let key = Bytes::from(&b"key"[..]);
+
+    let encoded = encode_key(id, &key);
+    assert_eq!(decode_key(&encoded), (id, key.clone()));
+
+    let (lo, hi) = encode_bounds(id, Bound::Unbounded, Bound::Unbounded);
+    let bounds = (lo, hi);
+    use std::ops::RangeBounds;
+    assert!(bounds.contains(&encoded));
+    assert!(bounds.contains(&encode_key(id, &[0xFF; 16])));
+    assert!(!bounds.contains(&encode_key(id.next(), &[])));
+    assert!(!bounds.contains(&encode_key(CollectionId::DEFAULT, &[0xFF])));
+}
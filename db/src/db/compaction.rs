@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use drama::{Classification, Executor, TenantId};
+
+use super::Storage;
+use super::cache::BlockCache;
+use super::manifest::Manifest;
+use super::table::{Table, TableWriter};
+use crate::fs::Fs;
+use crate::util::Keys;
+use crate::{Bytes, Compression, Config};
+
+/// Counters describing the work done by compaction since the database
+/// was opened.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    /// The number of completed compactions.
+    pub compactions: u64,
+    /// The number of tables that were merged into others.
+    pub tables_merged: u64,
+    /// Bytes of table data read by compaction.
+    pub bytes_read: u64,
+    /// Bytes of table files written by compaction.
+    pub bytes_written: u64,
+    /// Versions that were dropped because a newer table overwrote them.
+    pub versions_dropped: u64,
+    /// Tombstones that were dropped because no older table remained for
+    /// them to shadow.
+    pub tombstones_dropped: u64,
+}
+
+/// Merges flushed tables in the background so that reads consult few
+/// tables and overwritten data does not accumulate on disk.
+///
+/// Compaction runs on its own [`Executor`] as `Write` work. Tables are
+/// tiered by size: once a run of at least `trigger_tables` adjacent
+/// tables exists in which no table is larger than the newer tables of the
+/// run together, the run is merged into one table that takes its place,
+/// keeping only the newest version of each key. At most twice
+/// `trigger_tables` tables are merged at once, so a large old table is
+/// only rewritten once enough newer data has accumulated above it.
+/// Tombstones are dropped only when the run holds the oldest table, as
+/// nothing older remains for them to shadow. Tables only hold state at
+/// or before the horizon at the time they were flushed, so the merged
+/// table is correct for every live snapshot.
+///
+/// Merged entries are streamed into the output table one data block at a
+/// time, so a compaction holds a data block of each input in memory.
+pub(crate) struct Compactor {
+    shared: Arc<Shared>,
+    executor: Executor,
+    tenant_id: TenantId,
+}
+
+struct Shared {
+    fs: Arc<dyn Fs + Send + Sync>,
+    cache: Arc<BlockCache>,
+    compression: Compression,
+    keys: Keys,
+    directory: PathBuf,
+    storage: Arc<Storage>,
+    manifest: Arc<Mutex<Manifest>>,
+    trigger_tables: usize,
+    /// Zero means unlimited.
+    bytes_per_second: AtomicU64,
+    /// Set while a background compaction is queued or running.
+    scheduled: AtomicBool,
+    shutting_down: AtomicBool,
+    /// Held for the duration of a compaction.
+    compacting: Mutex<()>,
+    stats: Mutex<CompactionStats>,
+}
+
+impl Compactor {
+    pub(crate) fn new(
+        config: &Config,
+        fs: Arc<dyn Fs + Send + Sync>,
+        cache: Arc<BlockCache>,
+        storage: Arc<Storage>,
+        manifest: Arc<Mutex<Manifest>>,
+        tenant_id: TenantId,
+    ) -> Compactor {
+        let bytes_per_second = config.compaction_bytes_per_second.unwrap_or(0);
+
+        Compactor {
+            shared: Arc::new(Shared {
+                fs,
+                cache,
+                compression: config.compression,
+                keys: Keys::new(config),
+                directory: config.path.clone(),
+                storage,
+                manifest,
+                trigger_tables: config.compaction_trigger_tables,
+                bytes_per_second: AtomicU64::new(bytes_per_second),
+                scheduled: AtomicBool::new(false),
+                shutting_down: AtomicBool::new(false),
+                compacting: Mutex::new(()),
+                stats: Mutex::new(CompactionStats::default()),
+            }),
+            executor: Executor::new(1),
+            tenant_id,
+        }
+    }
+
+    /// Queues a background compaction if there are enough tables and one
+    /// is not already queued.
+    pub(crate) fn maybe_schedule(&self) {
+        if self.shared.storage.tables().len() < self.shared.trigger_tables
+            || self.shared.scheduled.swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+
+        let shared = self.shared.clone();
+
+        // The result is received by no one. A failed compaction leaves
+        // the existing tables in place and is retried after a later
+        // flush.
+        drop(
+            self.executor
+                .spawn(self.tenant_id, Classification::Write, move || {
+                    let _ = shared.compact_tiers();
+                    shared.scheduled.store(false, Ordering::Release);
+                }),
+        );
+    }
+
+    /// Merges every table into one, waiting for the result.
+    pub(crate) fn compact(&self) -> io::Result<()> {
+        let shared = self.shared.clone();
+
+        self.executor
+            .spawn(self.tenant_id, Classification::Write, move || {
+                shared.compact_all()
+            })
+            .recv()
+            .expect("compaction thread crashed")
+    }
+
+    pub(crate) fn stats(&self) -> CompactionStats {
+        *self.shared.stats.lock().unwrap()
+    }
+
+    pub(crate) fn set_rate_limit(&self, bytes_per_second: Option<u64>) {
+        self.shared
+            .bytes_per_second
+            .store(bytes_per_second.unwrap_or(0), Ordering::Relaxed);
+    }
+}
+
+impl Drop for Compactor {
+    fn drop(&mut self) {
+        // stop a running compaction at its next block rather than letting
+        // the executor's shutdown wait for all of it
+        self.shared.shutting_down.store(true, Ordering::Release);
+    }
+}
+
+/// Reads the entries of a table one data block at a time.
+struct TableCursor {
+    table: Arc<Table>,
+    next_block: usize,
+    entries: VecDeque<(Bytes, Option<Bytes>)>,
+}
+
+impl TableCursor {
+    /// The next entry, reading the next data block if needed and adding
+    /// the bytes read to `bytes_read`.
+    fn peek(&mut self, bytes_read: &mut u64) -> io::Result<Option<&(Bytes, Option<Bytes>)>> {
+        while self.entries.is_empty() && self.next_block < self.table.block_count() {
+            let entries = self.table.read_block_entries(self.next_block)?;
+            self.entries.extend(entries);
+            *bytes_read += self.table.block_len(self.next_block);
+            self.next_block += 1;
+        }
+
+        Ok(self.entries.front())
+    }
+}
+
+/// The first run of adjacent tables, from the newest, that holds at
+/// least `trigger_tables` tables and in which no table is larger than the
+/// newer tables of the run together. Runs hold at most twice
+/// `trigger_tables` tables.
+fn pick_run(tables: &[Arc<Table>], trigger_tables: usize) -> Option<Range<usize>> {
+    let trigger_tables = trigger_tables.max(2);
+    let max_tables = trigger_tables.saturating_mul(2);
+
+    for start in 0..tables.len() {
+        let mut run_bytes = tables[start].len;
+        let mut end = start + 1;
+
+        while end < tables.len() && end - start < max_tables && tables[end].len <= run_bytes {
+            run_bytes += tables[end].len;
+            end += 1;
+        }
+
+        if end - start >= trigger_tables {
+            return Some(start..end);
+        }
+    }
+
+    None
+}
+
+impl Shared {
+    /// Merges runs of similarly sized tables until none is left to merge.
+    fn compact_tiers(&self) -> io::Result<()> {
+        let _compacting = self.compacting.lock().unwrap();
+
+        loop {
+            let tables = self.storage.tables();
+            let Some(run) = pick_run(&tables, self.trigger_tables) else {
+                return Ok(());
+            };
+
+            self.merge(&tables, run)?;
+        }
+    }
+
+    /// Merges every table into one.
+    fn compact_all(&self) -> io::Result<()> {
+        let _compacting = self.compacting.lock().unwrap();
+
+        let tables = self.storage.tables();
+        if tables.len() < 2 {
+            return Ok(());
+        }
+
+        self.merge(&tables, 0..tables.len())
+    }
+
+    /// Replaces the tables in `run`, a range of `tables`, with one table
+    /// holding the newest version of each of their keys.
+    fn merge(&self, tables: &[Arc<Table>], run: Range<usize>) -> io::Result<()> {
+        let inputs = &tables[run.clone()];
+        // nothing older remains for a tombstone to shadow
+        let drop_tombstones = run.end == tables.len();
+
+        let output_id = {
+            let mut manifest = self.manifest.lock().unwrap();
+            let id = manifest.next_table_id;
+            manifest.next_table_id += 1;
+            id
+        };
+
+        let mut writer = TableWriter::new(
+            self.fs.clone(),
+            self.cache.clone(),
+            self.compression,
+            &self.keys,
+            &self.directory,
+            output_id,
+            inputs.iter().map(|input| input.bloom_capacity()).sum(),
+        );
+
+        let mut cursors: Vec<TableCursor> = inputs
+            .iter()
+            .map(|table| TableCursor {
+                table: table.clone(),
+                next_block: 0,
+                entries: VecDeque::new(),
+            })
+            .collect();
+
+        let started = Instant::now();
+        let mut bytes_read = 0;
+        let mut versions_dropped = 0;
+        let mut tombstones_dropped = 0;
+
+        loop {
+            if self.shutting_down.load(Ordering::Acquire) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "compaction was interrupted by shutdown",
+                ));
+            }
+
+            // Cursors are ordered from the newest table to the oldest, so
+            // the first cursor holding the lowest key has its newest
+            // version.
+            let mut lowest: Option<(usize, Bytes)> = None;
+            for (i, cursor) in cursors.iter_mut().enumerate() {
+                if let Some((key, _)) = cursor.peek(&mut bytes_read)?
+                    && lowest
+                        .as_ref()
+                        .is_none_or(|(_, lowest_key)| key < lowest_key)
+                {
+                    lowest = Some((i, key.clone()));
+                }
+            }
+
+            let Some((newest, key)) = lowest else {
+                break;
+            };
+
+            let (_, value_opt) = cursors[newest].entries.pop_front().unwrap();
+
+            for cursor in &mut cursors[newest + 1..] {
+                if cursor
+                    .peek(&mut bytes_read)?
+                    .is_some_and(|(k, _)| *k == key)
+                {
+                    cursor.entries.pop_front();
+                    versions_dropped += 1;
+                }
+            }
+
+            if value_opt.is_none() && drop_tombstones {
+                tombstones_dropped += 1;
+            } else {
+                writer.push(key, value_opt)?;
+            }
+
+            self.throttle(started, bytes_read);
+        }
+
+        let output_opt = writer.finish()?.map(Arc::new);
+
+        // Swap the merged table in where the run was. Tables flushed while
+        // merging are newer than every input, and stay in front of it.
+        let mut manifest = self.manifest.lock().unwrap();
+
+        let mut tables = manifest.tables.clone();
+        let position = tables
+            .iter()
+            .position(|(id, _)| *id == inputs[0].id)
+            .expect("compacted table is missing from the manifest");
+        tables.retain(|(id, _)| !inputs.iter().any(|input| input.id == *id));
+        if let Some(output) = &output_opt {
+            tables.insert(position, (output.id, output.len));
+        }
+
+        let flushed = manifest.flushed.expect("tables exist without a flush");
+
+        if let Err(e) = manifest.write(&self.fs, &self.directory, flushed, tables) {
+            if let Some(output) = output_opt {
+                output.mark_obsolete();
+            }
+            return Err(e);
+        }
+
+        let bytes_written = output_opt.as_ref().map_or(0, |output| output.len);
+
+        self.storage.replace_tables(inputs, output_opt);
+        drop(manifest);
+
+        for input in inputs {
+            input.mark_obsolete();
+        }
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.compactions += 1;
+        stats.tables_merged += inputs.len() as u64;
+        stats.bytes_read += bytes_read;
+        stats.bytes_written += bytes_written;
+        stats.versions_dropped += versions_dropped;
+        stats.tombstones_dropped += tombstones_dropped;
+
+        Ok(())
+    }
+
+    /// Sleeps until reading `bytes_read` bytes since `started` is within
+    /// the rate limit.
+    fn throttle(&self, started: Instant, bytes_read: u64) {
+        let bytes_per_second = self.bytes_per_second.load(Ordering::Relaxed);
+        if bytes_per_second == 0 {
+            return;
+        }
+
+        let allowed = Duration::from_secs_f64(bytes_read as f64 / bytes_per_second as f64);
+        if let Some(ahead) = allowed.checked_sub(started.elapsed()) {
+            std::thread::sleep(ahead);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::ops::{Bound, RangeBounds};
+
+use super::storage::SnapshotLsn;
+use crate::{Bytes, Lsn, WriteBatch};
+
+/// Returned from [`Tx::commit`](super::Tx::commit) when a transaction
+/// read data that was modified by another transaction that committed
+/// after it began. The transaction had no effect and may be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict;
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "transaction conflicted with a concurrent commit")
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+/// The keys and key ranges that a transaction observed in storage.
+#[derive(Default)]
+pub(crate) struct ReadSet {
+    keys: BTreeSet<Bytes>,
+    ranges: Vec<(Bound<Bytes>, Bound<Bytes>)>,
+}
+
+impl ReadSet {
+    pub(crate) fn record_key(&mut self, key: &Bytes) {
+        if !self.keys.contains(key) {
+            self.keys.insert(key.clone());
+        }
+    }
+
+    pub(crate) fn record_range(&mut self, lo: &Bound<Bytes>, hi: &Bound<Bytes>) {
+        self.ranges.push((lo.clone(), hi.clone()));
+    }
+
+    fn intersects(&self, written_keys: &[Bytes]) -> bool {
+        written_keys.iter().any(|key| {
+            self.keys.contains(key) || self.ranges.iter().any(|range| range.contains(key))
+        })
+    }
+}
+
+/// Tracks the keys written by recent commits so that transactions which
+/// began before them can be validated.
+#[derive(Default)]
+pub(crate) struct CommitHistory {
+    recent: VecDeque<(Lsn, Vec<Bytes>)>,
+}
+
+impl CommitHistory {
+    /// Checks whether any commit after the snapshot `start` wrote to
+    /// something in `read_set`.
+    pub(crate) fn validate(&self, start: SnapshotLsn, read_set: &ReadSet) -> Result<(), Conflict> {
+        let conflicts = self
+            .recent
+            .iter()
+            .filter(|(lsn, _)| Some(*lsn) > start)
+            .any(|(_, written_keys)| read_set.intersects(written_keys));
+
+        if conflicts { Err(Conflict) } else { Ok(()) }
+    }
+
+    pub(crate) fn record_commit(&mut self, lsn: Lsn, batch: &WriteBatch) {
+        self.recent
+            .push_back((lsn, batch.keys().cloned().collect()));
+    }
+
+    /// Drops commits that no snapshot at or after `horizon` can conflict
+    /// with.
+    pub(crate) fn prune(&mut self, horizon: SnapshotLsn) {
+        while let Some((lsn, _)) = self.recent.front() {
+            if Some(*lsn) > horizon {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+}
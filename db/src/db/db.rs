@@ -1,43 +1,559 @@
 use std::collections::BTreeMap;
 use std::io;
-use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 
-pub use super::{Transactor, Tx};
-pub use crate::util::{Bytes, WriteBatch};
-use crate::Config;
+use terrors::OneOf;
+
+use super::collection::{catalog_log_tail_key, encode_key};
+use super::log::{decode_seq, encode_seq};
+use super::manifest::Manifest;
+use super::open::referenced_objects;
+use super::table::{Table, table_path};
+use super::{
+    BlockCache, CacheStats, Collection, CommitHistory, CompactionStats, Compactor, Conflict, Log,
+    ReadSet, Rejected, Snapshot, SnapshotLsn, Storage, Subscriber, Subscribers, TransactorContext,
+    TransactorResult, Wal,
+};
+pub use super::{InterestFilter, Transactor, Tx};
+use crate::fs::{Fs, LocalFs, link_or_copy};
+use crate::object_store::{
+    ObjectId, ObjectMetadata, ObjectStats, ObjectStore, decode_object_ref, encode_object_ref,
+};
+pub use crate::util::WriteBatch;
+use crate::util::{Keys, block_on};
+use crate::{Bytes, CollectionId, Config};
 
 pub struct Db {
     pub(crate) config: Config,
-    pub(crate) transactors: Vec<Transactor>,
-    pub(crate) storage: Arc<Mutex<BTreeMap<Bytes, Bytes>>>,
+    pub(crate) keys: Keys,
+    pub(crate) fs: Arc<dyn Fs + Send + Sync>,
+    pub(crate) transactors: RwLock<Vec<Transactor>>,
+    pub(crate) storage: Arc<Storage>,
+    /// Held for the duration of a flush, which serializes flushes.
+    pub(crate) manifest: Arc<Mutex<Manifest>>,
+    pub(crate) compactor: Compactor,
+    pub(crate) cache: Arc<BlockCache>,
+    pub(crate) wal: Mutex<Wal>,
+    pub(crate) history: Mutex<CommitHistory>,
+    pub(crate) objects: ObjectStore,
+    pub(crate) subscribers: Subscribers,
 }
 
 impl Db {
     pub fn tx(&self) -> Tx<'_> {
         Tx {
+            snapshot: self.snapshot(),
+            read_set: ReadSet::default(),
+            write_batches: BTreeMap::new(),
+            log_appends: BTreeMap::new(),
+            object_writes: BTreeMap::new(),
+        }
+    }
+
+    /// Open the collection with the given name, creating it if it does
+    /// not exist yet.
+    pub fn open_collection(&self, name: &str) -> io::Result<Collection> {
+        self.transaction(|tx| tx.create_collection(name))
+    }
+
+    /// Open the log with the given name, creating it if it does not exist
+    /// yet.
+    pub fn open_log(&self, name: &str) -> io::Result<Log> {
+        self.transaction(|tx| tx.create_log(name))
+    }
+
+    /// Drop a collection and all of its data, returning `false` if it
+    /// did not exist.
+    pub fn drop_collection(&self, name: &str) -> io::Result<bool> {
+        self.transaction(|tx| tx.drop_collection(name))
+    }
+
+    /// Rename a collection. Existing handles to it remain valid.
+    pub fn rename_collection(&self, from: &str, to: &str) -> io::Result<()> {
+        self.transaction(|tx| tx.rename_collection(from, to))
+    }
+
+    /// The names of all collections, in order.
+    pub fn collection_names(&self) -> io::Result<Vec<String>> {
+        self.transaction(|tx| tx.collection_names())
+    }
+
+    /// Register a transactor that transforms every write batch committed
+    /// to `collection` that matches `filter`. A transactor may read the
+    /// current state of the database through its [`TransactorContext`],
+    /// and may reject the commit by returning an error, which is surfaced
+    /// from [`Tx::commit`] as [`Rejected`].
+    ///
+    /// Transactors of a collection are applied in the order in which
+    /// their names were first registered. That order is persisted, so
+    /// re-registering the same names after a restart applies them in the
+    /// same order regardless of the order of registration.
+    pub fn register_transactor<F>(
+        &self,
+        name: &str,
+        collection: &Collection,
+        filter: InterestFilter,
+        transformer: F,
+    ) -> io::Result<()>
+    where
+        F: 'static + Fn(&TransactorContext<'_>, WriteBatch) -> TransactorResult + Send + Sync,
+    {
+        let position = self.transaction(|tx| tx.transactor_position(name))?;
+
+        let mut transactors = self.transactors.write().unwrap();
+
+        if transactors.iter().any(|transactor| transactor.name == name) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("transactor {name:?} is already registered"),
+            ));
+        }
+
+        let index = transactors.partition_point(|transactor| transactor.position < position);
+        transactors.insert(
+            index,
+            Transactor::new(name, position, collection.id, filter, transformer),
+        );
+
+        Ok(())
+    }
+
+    /// Unregister a transactor, returning `false` if it was not
+    /// registered. Its position is forgotten, so registering the name
+    /// again places it after every other transactor.
+    pub fn unregister_transactor(&self, name: &str) -> io::Result<bool> {
+        let removed = {
+            let mut transactors = self.transactors.write().unwrap();
+            let len_before = transactors.len();
+            transactors.retain(|transactor| transactor.name != name);
+            transactors.len() != len_before
+        };
+
+        self.transaction(|tx| {
+            tx.forget_transactor(name);
+            Ok(())
+        })?;
+
+        Ok(removed)
+    }
+
+    /// Subscribe to the batches committed to the default collection that
+    /// match `filter`.
+    pub fn watch(&self, filter: InterestFilter) -> Subscriber {
+        self.watch_in(&Collection::DEFAULT, filter)
+    }
+
+    /// Subscribe to the batches committed to `collection` that match
+    /// `filter`. Only commits after this call are delivered.
+    pub fn watch_in(&self, collection: &Collection, filter: InterestFilter) -> Subscriber {
+        self.subscribers.subscribe(collection, filter)
+    }
+
+    /// Write the versions held in memory to a new table on disk, so that
+    /// the log before them can be deleted. This happens automatically
+    /// once [`Config::flush_threshold_bytes`] is exceeded.
+    ///
+    /// Versions that a live snapshot may still need to distinguish from
+    /// newer ones stay in memory until a later flush.
+    pub fn flush(&self) -> io::Result<()> {
+        self.flush_locked(self.manifest.lock().unwrap())
+    }
+
+    fn flush_locked(&self, mut manifest: MutexGuard<'_, Manifest>) -> io::Result<()> {
+        // Seal the log so that the segments holding flushed batches can
+        // be deleted as a whole. Every batch after the horizon is in the
+        // new active segment or in a sealed segment that is kept. The log
+        // is only sealed if the horizon moved past the last flush, as a
+        // pinned snapshot would otherwise seal a segment on every commit.
+        let through = {
+            let mut wal = self.wal.lock().unwrap();
+            let (Some(stable), Some(through)) = (self.storage.stable(), self.storage.horizon())
+            else {
+                return Ok(());
+            };
+
+            if Some(through) <= manifest.flushed {
+                return Ok(());
+            }
+
+            wal.rotate(stable)?;
+            through
+        };
+
+        let entries = self.storage.memtable_through(through);
+
+        let mut tables = manifest.tables.clone();
+        let table_opt = if entries.is_empty() {
+            None
+        } else {
+            let id = manifest.next_table_id;
+            manifest.next_table_id += 1;
+
+            let table = Table::write(
+                self.fs.clone(),
+                self.cache.clone(),
+                self.config.compression,
+                &self.keys,
+                &self.config.path,
+                id,
+                &entries,
+            )?;
+            tables.insert(0, (table.id, table.len));
+            Some(Arc::new(table))
+        };
+
+        if let Err(e) = manifest.write(&self.fs, &self.config.path, through, tables) {
+            if let Some(table) = table_opt {
+                let _ = self.fs.delete(table.path());
+            }
+            return Err(e);
+        }
+
+        self.storage.install_flushed(table_opt, through);
+        drop(manifest);
+
+        self.compactor.maybe_schedule();
+
+        self.wal.lock().unwrap().release_through(through)
+    }
+
+    /// Merge every table written by flushes into one, dropping overwritten
+    /// versions and tombstones. In the background, runs of similarly sized
+    /// tables are merged once [`Config::compaction_trigger_tables`] of them
+    /// exist.
+    pub fn compact(&self) -> io::Result<()> {
+        self.compactor.compact()
+    }
+
+    /// Writes a copy of the database to the directory `dest`, which must
+    /// not exist or be empty, that can be opened like any other database.
+    /// Commits continue while the copy is written, and it holds every
+    /// commit up to and including the last one that completed before
+    /// the checkpoint started. It is complete once this returns.
+    ///
+    /// Tables and sealed log segments are never modified, so they are
+    /// hard-linked into `dest`, or copied if it is on another file system
+    /// or the file system can't link them. The rest of the log is copied
+    /// up to the last commit, and the blobs of the objects that it
+    /// references are copied into a new heap. A checkpoint of an
+    /// encrypted database is encrypted with the same keys.
+    pub fn checkpoint<P: AsRef<Path>>(&self, dest: P) -> io::Result<()> {
+        let dest = std::path::absolute(dest)?;
+
+        std::fs::create_dir_all(&dest)?;
+        if std::fs::read_dir(&dest)?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("checkpoint directory {dest:?} is not empty"),
+            ));
+        }
+
+        // Files are written through a file system confined to the
+        // checkpoint
+        self.checkpoint_with_fs(&LocalFs::new(&dest)?, &dest)
+    }
+
+    /// Writes a checkpoint to `dest` through `fs`, which must hold `dest`,
+    /// linking in the files of the database that are read through the
+    /// database's own file system.
+    pub(crate) fn checkpoint_with_fs(&self, fs: &dyn Fs, dest: &Path) -> io::Result<()> {
+        let directory = &self.config.path;
+        let dest_path = |path: &Path| dest.join(path.file_name().unwrap());
+
+        // Holding the manifest lock keeps flushes and compactions from
+        // deleting the files of the checkpoint while they are linked
+        let manifest = self.manifest.lock().unwrap();
+
+        // The checkpoint is taken at the last commit. Pinning a snapshot
+        // there keeps the blobs that it references until they are copied.
+        let (snapshot, sealed, active, active_len) = {
+            let wal = self.wal.lock().unwrap();
+            let (sealed, active, active_len) = wal.segments_after(manifest.flushed);
+            (self.snapshot(), sealed, active, active_len)
+        };
+
+        let tables = manifest
+            .tables
+            .iter()
+            .map(|&(id, _)| table_path(directory, id));
+        for path in tables.chain(sealed) {
+            link_or_copy(&*self.fs, &path, fs, &dest_path(&path))?;
+        }
+
+        let read = |path: &Path, len: u64| -> io::Result<Vec<u8>> {
+            let mut buf = vec![0; len as usize];
+            self.fs.read_at_exact(path, 0, &mut buf)?;
+            Ok(buf)
+        };
+
+        let manifest_file = match manifest.path(directory) {
+            Some(path) => Some((read(&path, self.fs.len(&path)?)?, path)),
+            None => None,
+        };
+        let log = read(&active, active_len)?;
+        drop(manifest);
+
+        let referenced = referenced_objects(&self.storage, snapshot.at)?;
+        self.objects.copy_to(&dest.join("heap"), &referenced)?;
+        drop(snapshot);
+
+        // The manifest and log are written last, as they refer to the
+        // other files
+        if let Some((buf, path)) = manifest_file {
+            fs.create_unique(&dest_path(&path), &buf)?;
+        }
+        fs.create_unique(&dest_path(&active), &log)?;
+
+        fs.sync_dir(dest)?;
+
+        Ok(())
+    }
+
+    /// Counters describing the work done by compaction since the database
+    /// was opened.
+    pub fn compaction_stats(&self) -> CompactionStats {
+        self.compactor.stats()
+    }
+
+    /// Limit the rate at which compaction reads tables, or remove the
+    /// limit with `None`. Applies to a running compaction as well.
+    pub fn set_compaction_rate_limit(&self, bytes_per_second: Option<u64>) {
+        self.compactor.set_rate_limit(bytes_per_second);
+    }
+
+    /// Counters describing how often table reads were served by the block
+    /// cache since the database was opened.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Statistics about the stored objects and the heap files that hold
+    /// their bytes.
+    pub fn object_stats(&self) -> ObjectStats {
+        self.objects.stats()
+    }
+
+    /// Flushes after a commit once the memtable is large enough, unless a
+    /// flush is already running.
+    fn maybe_flush(&self) {
+        if self.storage.memtable_bytes() < self.config.flush_threshold_bytes {
+            return;
+        }
+
+        if let Ok(manifest) = self.manifest.try_lock() {
+            // The commit is durable in the log either way, and a failed
+            // flush is retried after the next commit.
+            let _ = self.flush_locked(manifest);
+        }
+    }
+
+    /// Run `f` in a new transaction and commit it, retrying on conflict.
+    pub(crate) fn transaction<F, R>(&self, mut f: F) -> io::Result<R>
+    where
+        F: FnMut(&mut Tx<'_>) -> io::Result<R>,
+    {
+        loop {
+            let mut tx = self.tx();
+            let ret = f(&mut tx)?;
+
+            let error = match tx.commit() {
+                Ok(()) => return Ok(ret),
+                Err(e) => match e.narrow::<Conflict, _>() {
+                    Ok(Conflict) => continue,
+                    Err(error) => error,
+                },
+            };
+
+            return Err(match error.narrow::<Rejected, _>() {
+                Ok(rejected) => io::Error::other(rejected),
+                Err(io_error) => io_error.take(),
+            });
+        }
+    }
+
+    /// Take a consistent, read-only view of the database as of the most
+    /// recent commit.
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot {
             db: self,
-            write_batch: BTreeMap::new(),
+            at: self.storage.pin(),
         }
     }
 
-    pub(crate) fn commit(&self, batch: WriteBatch) -> io::Result<()> {
-        // Apply transformations from transactors
-        let final_batch = self.apply_transactors(batch);
+    pub(crate) fn release_snapshot(&self, at: SnapshotLsn) {
+        self.storage.unpin(at);
+
+        let horizon = self.storage.horizon();
+        self.history.lock().unwrap().prune(horizon);
+        block_on(self.objects.collect_garbage(horizon));
+    }
+
+    pub(crate) fn commit(
+        &self,
+        mut tx: Tx<'_>,
+    ) -> Result<(), OneOf<(Conflict, Rejected, io::Error)>> {
+        // Write the blobs of new objects before anything refers to them,
+        // and without holding the log lock
+        let mut object_refs: BTreeMap<Bytes, Option<(ObjectId, ObjectMetadata)>> = BTreeMap::new();
+        let mut new_objects = vec![];
 
-        // Apply the batch to storage
-        let mut storage = self.storage.lock().unwrap();
-        for (key, value) in final_batch {
-            storage.insert(key, value);
+        for (key, bytes_opt) in std::mem::take(&mut tx.object_writes) {
+            let object_ref = bytes_opt.map(|bytes| {
+                let id = self.objects.allocate_id();
+                let metadata = ObjectMetadata::for_bytes(&bytes);
+                new_objects.push((id, metadata, bytes.inner.into_vec()));
+                (id, metadata)
+            });
+            object_refs.insert(key, object_ref);
         }
 
+        let new_ids: Vec<ObjectId> = new_objects.iter().map(|(id, _, _)| *id).collect();
+
+        let mut ret = block_on(self.objects.write_batch(new_objects)).map_err(OneOf::new);
+
+        if ret.is_ok() {
+            ret = self.commit_batches(tx, object_refs);
+        }
+
+        if ret.is_err() {
+            block_on(self.objects.delete_unreferenced(&new_ids));
+        }
+
+        ret
+    }
+
+    fn commit_batches(
+        &self,
+        tx: Tx<'_>,
+        object_refs: BTreeMap<Bytes, Option<(ObjectId, ObjectMetadata)>>,
+    ) -> Result<(), OneOf<(Conflict, Rejected, io::Error)>> {
+        // The log lock is held from validation until the batch is
+        // applied, which serializes commits and ensures that the order
+        // of the log always matches the order of application.
+        let mut wal = self.wal.lock().unwrap();
+
+        self.history
+            .lock()
+            .unwrap()
+            .validate(tx.snapshot.at, &tx.read_set)
+            .map_err(OneOf::new)?;
+
+        // Apply transformations from transactors, then merge the batches
+        // of every collection into a single batch of storage keys. The
+        // latest snapshot is only pinned while transactors run.
+        let mut final_batch = WriteBatch::new();
+        let mut published = vec![];
+        {
+            let latest = self.snapshot();
+            let publish = !self.subscribers.is_empty();
+
+            for (collection_id, batch) in tx.write_batches {
+                let batch = self
+                    .apply_transactors(&latest, collection_id, batch)
+                    .map_err(OneOf::new)?;
+
+                if publish && !batch.is_empty() {
+                    for (key, value_opt) in &batch {
+                        final_batch.insert(encode_key(collection_id, key), value_opt.clone());
+                    }
+                    published.push((collection_id, batch));
+                } else {
+                    for (key, value_opt) in batch {
+                        final_batch.insert(encode_key(collection_id, &key), value_opt);
+                    }
+                }
+            }
+        }
+
+        // Assign sequence numbers to log entries. Commits are serialized
+        // here, so reading each log's tail from the latest state yields
+        // dense sequence numbers without appenders conflicting.
+        for (log_id, entries) in tx.log_appends {
+            let tail_key = encode_key(CollectionId::CATALOG, &catalog_log_tail_key(log_id));
+
+            let mut next_seq = self
+                .storage
+                .get_latest(&tail_key)
+                .map_err(OneOf::new)?
+                .map_or(0, |tail| decode_seq(&tail));
+
+            for entry in entries {
+                final_batch.insert(encode_key(log_id, &encode_seq(next_seq)), Some(entry));
+                next_seq += 1;
+            }
+
+            final_batch.insert(tail_key, Some(encode_seq(next_seq)));
+        }
+
+        // Point the object namespace at the new blobs. The blobs of the
+        // objects that are replaced are removed once no snapshot can read
+        // them.
+        let mut replaced_objects = vec![];
+        for (key, object_ref) in object_refs {
+            let storage_key = encode_key(CollectionId::OBJECTS, &key);
+
+            if let Some(old_value) = self.storage.get_latest(&storage_key).map_err(OneOf::new)? {
+                replaced_objects.push(decode_object_ref(&old_value).0);
+            }
+
+            let value_opt = object_ref.map(|(id, metadata)| encode_object_ref(id, metadata));
+            final_batch.insert(storage_key, value_opt);
+        }
+
+        if final_batch.is_empty() {
+            return Ok(());
+        }
+
+        let lsn = self.storage.next_lsn();
+
+        // Make the batch durable before it becomes visible
+        wal.append(lsn, &final_batch).map_err(OneOf::new)?;
+
+        self.storage.apply(lsn, &final_batch);
+
+        // Subscribers are notified while the log lock is still held, so
+        // they receive events in commit order
+        for (collection_id, batch) in &published {
+            self.subscribers.publish(lsn, *collection_id, batch);
+        }
+
+        let horizon = self.storage.horizon();
+
+        let mut history = self.history.lock().unwrap();
+        history.record_commit(lsn, &final_batch);
+        history.prune(horizon);
+        drop(history);
+
+        for id in replaced_objects {
+            self.objects.defer_delete(lsn, id);
+        }
+        drop(wal);
+
+        block_on(self.objects.collect_garbage(horizon));
+
+        self.maybe_flush();
+
         Ok(())
     }
 
-    fn apply_transactors(&self, mut batch: WriteBatch) -> WriteBatch {
-        // Apply each transactor in sequence
-        for transactor in &self.transactors {
-            batch = transactor.apply(batch);
+    fn apply_transactors(
+        &self,
+        latest: &Snapshot<'_>,
+        collection_id: CollectionId,
+        mut batch: WriteBatch,
+    ) -> Result<WriteBatch, Rejected> {
+        let context = TransactorContext {
+            snapshot: latest,
+            collection: Collection { id: collection_id },
+        };
+
+        // Apply each of the collection's transactors in sequence
+        for transactor in self.transactors.read().unwrap().iter() {
+            if transactor.collection_id == collection_id {
+                batch = transactor.apply(&context, batch)?;
+            }
         }
-        batch
+        Ok(batch)
     }
 }
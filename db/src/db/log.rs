@@ -0,0 +1,55 @@
+use std::io;
+
+use super::{Collection, Range};
+use crate::{Bytes, CollectionId};
+
+/// A handle to a named, append-only log, returned by
+/// [`Db::open_log`](super::Db::open_log).
+///
+/// Entries appended with [`Tx::append`](super::Tx::append) are assigned
+/// dense, increasing sequence numbers (starting at 0) when their
+/// transaction commits, atomically with the rest of the transaction.
+/// Sequence numbers are never reused, even after a prefix of the log is
+/// truncated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Log {
+    pub(crate) id: CollectionId,
+}
+
+impl Log {
+    /// Entries are stored in a collection of their own, keyed by their
+    /// big-endian sequence number.
+    pub(crate) fn collection(&self) -> Collection {
+        Collection { id: self.id }
+    }
+}
+
+pub(crate) fn encode_seq(seq: u64) -> Bytes {
+    Bytes::from(&seq.to_be_bytes()[..])
+}
+
+pub(crate) fn decode_seq(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().expect("corrupt log sequence number"))
+}
+
+/// An ordered iterator over the `(sequence number, entry)` pairs of a
+/// [`Log`].
+pub struct LogIter<'a> {
+    pub(crate) range: Range<'a>,
+}
+
+impl<'a> Iterator for LogIter<'a> {
+    type Item = io::Result<(u64, Bytes)>;
+
+    fn next(&mut self) -> Option<io::Result<(u64, Bytes)>> {
+        let entry = self.range.next()?;
+        Some(entry.map(|(key, value)| (decode_seq(&key), value)))
+    }
+}
+
+impl<'a> DoubleEndedIterator for LogIter<'a> {
+    fn next_back(&mut self) -> Option<io::Result<(u64, Bytes)>> {
+        let entry = self.range.next_back()?;
+        Some(entry.map(|(key, value)| (decode_seq(&key), value)))
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::SnapshotLsn;
+use crate::Lsn;
+use crate::fs::Fs;
+use crate::util::{
+    Compression, FILE_HEADER_LEN, Keys, RecordType, check_file_header, read_batch, write_batch,
+    write_file_header,
+};
+
+const MANIFEST_PREFIX: &str = "manifest.";
+const TABLE_PREFIX: &str = "table.";
+
+/// The set of tables that make up the flushed state of the database,
+/// along with the `Lsn` that they were flushed through. Every batch at
+/// or before that `Lsn` is reflected in the tables, so log segments
+/// that end at or before it are no longer needed for recovery.
+///
+/// Each change writes a new manifest file with a higher sequence number
+/// and then deletes the old one, so a crash at any point leaves at
+/// least one complete manifest behind.
+pub(crate) struct Manifest {
+    keys: Keys,
+    seq: u64,
+    pub(crate) flushed: SnapshotLsn,
+    pub(crate) next_table_id: u64,
+    /// The id and length of each table, from newest to oldest.
+    pub(crate) tables: Vec<(u64, u64)>,
+}
+
+fn manifest_path(directory: &Path, seq: u64) -> PathBuf {
+    directory.join(format!("{MANIFEST_PREFIX}{seq:016x}"))
+}
+
+fn parse_suffix(path: &Path, prefix: &str) -> Option<u64> {
+    let suffix = path.file_name()?.to_str()?.strip_prefix(prefix)?;
+    if suffix.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(suffix, 16).ok()
+}
+
+fn corrupt(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("manifest file {path:?} is corrupt"),
+    )
+}
+
+impl Manifest {
+    /// Reads the newest manifest in `directory`, deleting older manifests
+    /// and any table files that it does not reference, which are left
+    /// behind by flushes that crashed before their manifest was written.
+    pub(crate) fn recover(
+        fs: &Arc<dyn Fs + Send + Sync>,
+        keys: Keys,
+        directory: &Path,
+    ) -> io::Result<Manifest> {
+        let mut manifest_seqs = BTreeSet::new();
+        let mut table_ids = BTreeSet::new();
+
+        for path in fs.list(directory, MANIFEST_PREFIX)? {
+            manifest_seqs.extend(parse_suffix(&path, MANIFEST_PREFIX));
+        }
+
+        for path in fs.list(directory, TABLE_PREFIX)? {
+            table_ids.extend(parse_suffix(&path, TABLE_PREFIX));
+        }
+
+        let manifest = match manifest_seqs.last() {
+            Some(&seq) => Manifest::read(fs, keys, directory, seq)?,
+            None => Manifest {
+                keys,
+                seq: 0,
+                flushed: None,
+                next_table_id: 0,
+                tables: vec![],
+            },
+        };
+
+        for &seq in manifest_seqs.range(..manifest.seq) {
+            fs.delete(&manifest_path(directory, seq))?;
+        }
+
+        for id in table_ids {
+            if !manifest.tables.iter().any(|&(table_id, _)| table_id == id) {
+                fs.delete(&super::table::table_path(directory, id))?;
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    fn read(
+        fs: &Arc<dyn Fs + Send + Sync>,
+        keys: Keys,
+        directory: &Path,
+        seq: u64,
+    ) -> io::Result<Manifest> {
+        let path = manifest_path(directory, seq);
+
+        let mut buf = vec![0; fs.len(&path)? as usize];
+        fs.read_at_exact(&path, 0, &mut buf)?;
+
+        let cipher = keys.for_file(check_file_header(&buf, "manifest")?)?;
+
+        let mut sub_frames = read_batch(
+            RecordType::Manifest,
+            &cipher,
+            FILE_HEADER_LEN as u64,
+            &mut &buf[FILE_HEADER_LEN..],
+        )?
+        .into_iter();
+
+        let flushed = sub_frames
+            .next()
+            .and_then(|lsn_frame| <[u8; 8]>::try_from(lsn_frame).ok())
+            .and_then(Lsn::from_le_bytes)
+            .ok_or_else(|| corrupt(&path))?;
+
+        let mut tables = vec![];
+        for table_frame in sub_frames {
+            if table_frame.len() != 16 {
+                return Err(corrupt(&path));
+            }
+            let (id, len) = table_frame.split_at(8);
+            tables.push((
+                u64::from_le_bytes(id.try_into().unwrap()),
+                u64::from_le_bytes(len.try_into().unwrap()),
+            ));
+        }
+
+        let next_table_id = tables.iter().map(|&(id, _)| id + 1).max().unwrap_or(0);
+
+        Ok(Manifest {
+            keys,
+            seq,
+            flushed: Some(flushed),
+            next_table_id,
+            tables,
+        })
+    }
+
+    /// The file holding this manifest, or `None` before the first flush.
+    pub(crate) fn path(&self, directory: &Path) -> Option<PathBuf> {
+        self.flushed.map(|_| manifest_path(directory, self.seq))
+    }
+
+    /// Durably replaces the manifest with one recording `tables` as the
+    /// flushed state through `flushed`.
+    pub(crate) fn write(
+        &mut self,
+        fs: &Arc<dyn Fs + Send + Sync>,
+        directory: &Path,
+        flushed: Lsn,
+        tables: Vec<(u64, u64)>,
+    ) -> io::Result<()> {
+        let mut sub_frames = vec![flushed.to_le_bytes().to_vec()];
+        for &(id, len) in &tables {
+            let mut table_frame = id.to_le_bytes().to_vec();
+            table_frame.extend_from_slice(&len.to_le_bytes());
+            sub_frames.push(table_frame);
+        }
+
+        let cipher = self.keys.for_writing();
+
+        let mut buf = vec![];
+        write_file_header(&cipher, &mut buf)?;
+        write_batch(
+            RecordType::Manifest,
+            Compression::None,
+            &cipher,
+            buf.len() as u64,
+            sub_frames.iter(),
+            &mut buf,
+        )?;
+
+        let old_seq = self.seq;
+        fs.create_unique(&manifest_path(directory, old_seq + 1), &buf)?;
+
+        // nothing is written before the first flush
+        let had_manifest = self.flushed.is_some();
+
+        self.seq = old_seq + 1;
+        self.flushed = Some(flushed);
+        self.tables = tables;
+
+        if had_manifest {
+            fs.delete(&manifest_path(directory, old_seq))?;
+        }
+
+        Ok(())
+    }
+}
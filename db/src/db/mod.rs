@@ -1,11 +1,39 @@
+mod cache;
+mod collection;
+mod compaction;
+mod conflict;
 mod db;
+mod log;
+mod manifest;
 mod open;
 mod range;
+mod snapshot;
+mod storage;
+mod table;
 mod transactor;
 mod tx;
+mod wal;
+mod watch;
 
+pub use cache::CacheStats;
+pub use collection::Collection;
+pub use compaction::CompactionStats;
+pub use conflict::Conflict;
 pub use db::Db;
+pub use log::{Log, LogIter};
 pub use open::open;
 pub use range::Range;
-pub use transactor::{InterestFilter, Transactor};
+pub use snapshot::Snapshot;
+pub use transactor::{InterestFilter, Rejected, Transactor, TransactorContext, TransactorResult};
 pub use tx::Tx;
+pub use watch::{Subscriber, WatchEvent};
+
+pub(crate) use open::open_with_config;
+pub(crate) use storage::SnapshotLsn;
+
+use cache::BlockCache;
+use compaction::Compactor;
+use conflict::{CommitHistory, ReadSet};
+use storage::Storage;
+use wal::Wal;
+use watch::Subscribers;
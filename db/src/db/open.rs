@@ -1,13 +1,361 @@
-use crate::{Config, Db};
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, RwLock};
+
+use drama::TenantId;
+
+use super::collection::encode_bounds;
+use super::manifest::Manifest;
+use super::table::Table;
+use super::{BlockCache, CommitHistory, Compactor, SnapshotLsn, Storage, Subscribers, Wal};
+use crate::fs::{Fs, LocalFs};
+use crate::heap::Heap;
+use crate::object_store::{ObjectId, ObjectStore, decode_object_ref};
+use crate::util::Keys;
+use crate::{CollectionId, Config, Db};
+
+const OBJECT_STORE_THREADS: usize = 2;
 
 pub fn open<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Db> {
-    let config = Config {
-        path: path.as_ref().to_path_buf(),
-    };
+    Config::new(path.as_ref()).open()
+}
+
+pub(crate) fn open_with_config(config: Config) -> std::io::Result<Db> {
+    std::fs::create_dir_all(&config.path)?;
+
+    let mut local_fs = LocalFs::new(&config.path)?;
+    local_fs.lock()?;
+    local_fs.remove_temporary_files()?;
+
+    open_with_fs(config, Arc::new(local_fs))
+}
+
+/// Opens the database stored in `fs`. Objects are always stored in a
+/// heap on the local file system, in `config.path`.
+pub(crate) fn open_with_fs(config: Config, fs: Arc<dyn Fs + Send + Sync>) -> std::io::Result<Db> {
+    let keys = Keys::new(&config);
+
+    let (mut wal, recovered_batches) =
+        Wal::recover(fs.clone(), &config.path, config.compression, keys.clone())?;
+
+    let manifest = Manifest::recover(&fs, keys.clone(), &config.path)?;
+
+    let cache = Arc::new(BlockCache::new(config.block_cache_bytes));
+
+    let mut tables = vec![];
+    for &(id, len) in &manifest.tables {
+        tables.push(Arc::new(Table::open(
+            fs.clone(),
+            cache.clone(),
+            &keys,
+            &config.path,
+            id,
+            len,
+        )?));
+    }
+
+    // Replay the log after the flushed state. Batches were logged after
+    // transactors were applied, so they are written to storage as-is.
+    let storage = Arc::new(Storage::recover(tables, manifest.flushed));
+    for (lsn, batch) in recovered_batches {
+        if Some(lsn) > manifest.flushed {
+            storage.apply(lsn, &batch);
+        }
+    }
+
+    if let Some(flushed) = manifest.flushed {
+        wal.release_through(flushed)?;
+    }
+
+    let referenced_objects = referenced_objects(&storage, storage.horizon())?;
+    // Background work of each database is scheduled as its own tenant
+    let tenant_id = TenantId::new(&config.path);
+
+    let objects = ObjectStore::recover(
+        Heap::open(&config.path.join("heap"))?,
+        keys.clone(),
+        OBJECT_STORE_THREADS,
+        tenant_id,
+        &referenced_objects,
+    )?;
+
+    let manifest = Arc::new(Mutex::new(manifest));
+
+    let compactor = Compactor::new(
+        &config,
+        fs.clone(),
+        cache.clone(),
+        storage.clone(),
+        manifest.clone(),
+        tenant_id,
+    );
 
     Ok(Db {
         config,
-        transactors: Vec::new(),
-        storage: std::sync::Arc::new(std::sync::Mutex::new(std::collections::BTreeMap::new())),
+        keys,
+        fs,
+        transactors: RwLock::new(Vec::new()),
+        storage,
+        manifest,
+        compactor,
+        cache,
+        wal: Mutex::new(wal),
+        history: Mutex::new(CommitHistory::default()),
+        objects,
+        subscribers: Subscribers::default(),
     })
 }
+
+/// The blobs referenced by the object namespace as of `at`.
+pub(super) fn referenced_objects(
+    storage: &Storage,
+    at: SnapshotLsn,
+) -> std::io::Result<BTreeSet<ObjectId>> {
+    let (mut lo, hi) = encode_bounds(CollectionId::OBJECTS, Bound::Unbounded, Bound::Unbounded);
+
+    let mut referenced = BTreeSet::new();
+
+    while let Some((key, value)) = storage.first_in((lo.as_ref(), hi.as_ref()), at)? {
+        referenced.insert(decode_object_ref(&value).0);
+        lo = Bound::Excluded(key);
+    }
+
+    Ok(referenced)
+}
+
+#[test]
+fn recover_after_crash() {
+    use crate::fs::MemFs;
+
+    let path = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+    let log = path.join("log");
+    let key = |i: u32| i.to_be_bytes();
+
+    for seed in 0..8 {
+        let _ = std::fs::remove_dir_all(&path);
+        let mem_fs = MemFs::with_seed(seed);
+
+        {
+            let db = open_with_fs(Config::new(&path), Arc::new(mem_fs.clone())).unwrap();
+
+            for i in 0..100 {
+                let mut tx = db.tx();
+                tx.insert(&key(i), &[i as u8; 64]).unwrap();
+                tx.commit().unwrap();
+
+                if i == 50 {
+                    db.flush().unwrap();
+                }
+            }
+
+            // A commit whose frame was written but not yet synced when
+            // the machine lost power
+            let synced_len = mem_fs.len(&log).unwrap();
+            let mut tx = db.tx();
+            tx.insert(&key(100), &[100; 64]).unwrap();
+            tx.commit().unwrap();
+
+            let mut frame = vec![0; (mem_fs.len(&log).unwrap() - synced_len) as usize];
+            mem_fs
+                .read_at_exact(&log, synced_len as usize, &mut frame)
+                .unwrap();
+            mem_fs.truncate(&log, synced_len).unwrap();
+            mem_fs.sync(&log).unwrap();
+            mem_fs.append(&log, &frame).unwrap();
+        }
+
+        mem_fs.crash();
+
+        let db = open_with_fs(Config::new(&path), Arc::new(mem_fs.clone())).unwrap();
+        let mut tx = db.tx();
+
+        for i in 0..100 {
+            assert_eq!(tx.get(&key(i)).unwrap().unwrap(), [i as u8; 64]);
+        }
+
+        // the torn commit is either recovered whole or not at all
+        if let Some(value) = tx.get(&key(100)).unwrap() {
+            assert_eq!(value, [100; 64]);
+        }
+    }
+
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn recover_torn_tail_with_unwritten_page() {
+    use crate::fs::MemFs;
+
+    const PAGE: u64 = 4096;
+
+    let path = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+    let log = path.join("log");
+    let key = |i: u32| i.to_be_bytes();
+    let value: Vec<u8> = (0..5 * PAGE as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+        .collect();
+
+    let _ = std::fs::remove_dir_all(&path);
+    let mem_fs = MemFs::default();
+
+    let synced_len = {
+        let db = open_with_fs(Config::new(&path), Arc::new(mem_fs.clone())).unwrap();
+
+        for i in 0..10 {
+            let mut tx = db.tx();
+            tx.insert(&key(i), &[i as u8; 64]).unwrap();
+            tx.commit().unwrap();
+        }
+
+        // the last commit spans several pages of the log
+        let synced_len = mem_fs.len(&log).unwrap();
+        let mut tx = db.tx();
+        tx.insert(b"last", &value).unwrap();
+        tx.commit().unwrap();
+
+        synced_len
+    };
+
+    let mut written = vec![0; mem_fs.len(&log).unwrap() as usize];
+    mem_fs.read_at_exact(&log, 0, &mut written).unwrap();
+    assert!(written.len() as u64 - synced_len > 3 * PAGE);
+
+    // An interrupted append may persist every page of the last frame but
+    // one, which reads as zeroes, whether it holds the header or the body
+    for page in synced_len / PAGE..=(written.len() as u64 - 1) / PAGE {
+        let start = (page * PAGE).max(synced_len) as usize;
+        let end = ((page + 1) * PAGE).min(written.len() as u64) as usize;
+        let mut torn = written.clone();
+        torn[start..end].fill(0);
+
+        mem_fs.truncate(&log, 0).unwrap();
+        mem_fs.append(&log, &torn).unwrap();
+        mem_fs.sync(&log).unwrap();
+
+        let db = open_with_fs(Config::new(&path), Arc::new(mem_fs.clone())).unwrap();
+        let snapshot = db.snapshot();
+
+        for i in 0..10 {
+            assert_eq!(snapshot.get(&key(i)).unwrap().unwrap(), [i as u8; 64]);
+        }
+        assert!(snapshot.get(b"last").unwrap().is_none());
+    }
+
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn checkpoint_after_crash() {
+    use crate::fs::MemFs;
+
+    let path = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+    let source = path.join("source");
+    let dest = path.join("checkpoint");
+    let key = |i: u32| i.to_be_bytes();
+
+    let _ = std::fs::remove_dir_all(&path);
+    let mem_fs = MemFs::default();
+
+    {
+        let db = open_with_fs(Config::new(&source), Arc::new(mem_fs.clone())).unwrap();
+
+        // tables, a sealed segment kept by a snapshot, and an active
+        // segment
+        let mut snapshot = None;
+        for i in 0..100 {
+            let mut tx = db.tx();
+            tx.insert(&key(i), &[i as u8; 64]).unwrap();
+            if i % 10 == 0 {
+                tx.put_object(&key(i), &[i as u8; 128]);
+            }
+            tx.commit().unwrap();
+
+            match i {
+                30 => db.flush().unwrap(),
+                60 => snapshot = Some(db.snapshot()),
+                80 => db.flush().unwrap(),
+                _ => {}
+            }
+        }
+
+        db.checkpoint_with_fs(&mem_fs, &dest).unwrap();
+        drop(snapshot);
+        assert_eq!(mem_fs.list(&dest, "log.").unwrap().len(), 1);
+
+        // later commits are not in the checkpoint
+        let mut tx = db.tx();
+        tx.insert(&key(100), &[100; 64]).unwrap();
+        tx.commit().unwrap();
+        db.flush().unwrap();
+
+        // tables are shared rather than copied
+        let table = mem_fs.list(&source, "table.").unwrap()[0].clone();
+        assert!(
+            mem_fs
+                .exists(&dest.join(table.file_name().unwrap()))
+                .unwrap()
+        );
+    }
+
+    mem_fs.crash();
+
+    let db = open_with_fs(Config::new(&dest), Arc::new(mem_fs.clone())).unwrap();
+    let mut tx = db.tx();
+
+    for i in 0..100 {
+        assert_eq!(tx.get(&key(i)).unwrap().unwrap(), [i as u8; 64]);
+        if i % 10 == 0 {
+            assert_eq!(tx.get_object(&key(i)).unwrap().unwrap(), [i as u8; 128]);
+        }
+    }
+    assert!(tx.get(&key(100)).unwrap().is_none());
+
+    drop(tx);
+    drop(db);
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn checkpoint_without_hard_links() {
+    use crate::fs::MemFs;
+
+    let path = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+    let source = path.join("source");
+    let dest = path.join("checkpoint");
+    let key = |i: u32| i.to_be_bytes();
+
+    let _ = std::fs::remove_dir_all(&path);
+    let mem_fs = MemFs::default();
+    mem_fs.disable_hard_links();
+
+    {
+        let db = open_with_fs(Config::new(&source), Arc::new(mem_fs.clone())).unwrap();
+
+        for i in 0..20 {
+            let mut tx = db.tx();
+            tx.insert(&key(i), &[i as u8; 64]).unwrap();
+            tx.commit().unwrap();
+
+            if i == 10 {
+                db.flush().unwrap();
+            }
+        }
+
+        // tables are copied when they can't be linked
+        db.checkpoint_with_fs(&mem_fs, &dest).unwrap();
+        let tables = mem_fs.list(&dest, "table.").unwrap();
+        assert_eq!(tables.len(), 1);
+    }
+
+    mem_fs.crash();
+
+    let db = open_with_fs(Config::new(&dest), Arc::new(mem_fs.clone())).unwrap();
+    let snapshot = db.snapshot();
+    for i in 0..20 {
+        assert_eq!(snapshot.get(&key(i)).unwrap().unwrap(), [i as u8; 64]);
+    }
+
+    drop(snapshot);
+    drop(db);
+    std::fs::remove_dir_all(&path).unwrap();
+}
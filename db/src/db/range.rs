@@ -1 +1,146 @@
-pub struct Range {}
+use std::io;
+use std::ops::Bound;
+
+use super::Db;
+use super::collection::{decode_key, encode_bounds};
+use super::storage::SnapshotLsn;
+use crate::{Bytes, CollectionId, WriteBatch};
+
+/// An ordered iterator over a range of keys, as seen by a
+/// [`Tx`](super::Tx) or [`Snapshot`](super::Snapshot).
+///
+/// Keys written by a transaction shadow committed keys, so a transaction
+/// always observes its own uncommitted writes. Each step re-seeks past the
+/// last key returned from either end, so storage is only locked for the
+/// duration of a single `next` or `next_back` call. A table that can't
+/// be read is returned as an error.
+pub struct Range<'a> {
+    pub(crate) db: &'a Db,
+    pub(crate) at: SnapshotLsn,
+    pub(crate) collection: CollectionId,
+    pub(crate) write_batch: Option<&'a WriteBatch>,
+    pub(crate) lo: Bound<Bytes>,
+    pub(crate) hi: Bound<Bytes>,
+}
+
+impl<'a> Range<'a> {
+    fn is_exhausted(&self) -> bool {
+        match (&self.lo, &self.hi) {
+            (Bound::Included(lo), Bound::Included(hi)) => lo > hi,
+            (Bound::Included(lo), Bound::Excluded(hi))
+            | (Bound::Excluded(lo), Bound::Included(hi))
+            | (Bound::Excluded(lo), Bound::Excluded(hi)) => lo >= hi,
+            _ => false,
+        }
+    }
+
+    fn bounds(&self) -> (Bound<&Bytes>, Bound<&Bytes>) {
+        (self.lo.as_ref(), self.hi.as_ref())
+    }
+
+    fn first_in_storage(&self) -> io::Result<Option<(Bytes, Bytes)>> {
+        let (lo, hi) = encode_bounds(self.collection, self.lo.as_ref(), self.hi.as_ref());
+        let found = self
+            .db
+            .storage
+            .first_in((lo.as_ref(), hi.as_ref()), self.at)?;
+        Ok(found.map(|(key, value)| (decode_key(&key).1, value)))
+    }
+
+    fn last_in_storage(&self) -> io::Result<Option<(Bytes, Bytes)>> {
+        let (lo, hi) = encode_bounds(self.collection, self.lo.as_ref(), self.hi.as_ref());
+        let found = self
+            .db
+            .storage
+            .last_in((lo.as_ref(), hi.as_ref()), self.at)?;
+        Ok(found.map(|(key, value)| (decode_key(&key).1, value)))
+    }
+}
+
+impl<'a> Iterator for Range<'a> {
+    type Item = io::Result<(Bytes, Bytes)>;
+
+    fn next(&mut self) -> Option<io::Result<(Bytes, Bytes)>> {
+        while !self.is_exhausted() {
+            let from_batch = self
+                .write_batch
+                .and_then(|wb| wb.range::<Bytes, _>(self.bounds()).next());
+
+            let from_storage = match self.first_in_storage() {
+                Ok(from_storage) => from_storage,
+                Err(e) => return Some(Err(e)),
+            };
+
+            // the write batch wins ties so that uncommitted writes
+            // (including removals) shadow committed ones
+            let (key, value) = match (from_batch, from_storage) {
+                (Some(b), Some(s)) if s.0 < *b.0 => (s.0, Some(s.1)),
+                (Some(b), _) => (b.0.clone(), b.1.clone()),
+                (None, Some(s)) => (s.0, Some(s.1)),
+                (None, None) => return None,
+            };
+
+            self.lo = Bound::Excluded(key.clone());
+
+            if let Some(value) = value {
+                return Some(Ok((key, value)));
+            }
+        }
+
+        None
+    }
+}
+
+impl<'a> DoubleEndedIterator for Range<'a> {
+    fn next_back(&mut self) -> Option<io::Result<(Bytes, Bytes)>> {
+        while !self.is_exhausted() {
+            let from_batch = self
+                .write_batch
+                .and_then(|wb| wb.range::<Bytes, _>(self.bounds()).next_back());
+
+            let from_storage = match self.last_in_storage() {
+                Ok(from_storage) => from_storage,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let (key, value) = match (from_batch, from_storage) {
+                (Some(b), Some(s)) if s.0 > *b.0 => (s.0, Some(s.1)),
+                (Some(b), _) => (b.0.clone(), b.1.clone()),
+                (None, Some(s)) => (s.0, Some(s.1)),
+                (None, None) => return None,
+            };
+
+            self.hi = Bound::Excluded(key.clone());
+
+            if let Some(value) = value {
+                return Some(Ok((key, value)));
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns the smallest key that is greater than every key beginning
+/// with `prefix`, or `None` if no such key exists (the prefix is empty
+/// or made entirely of `0xFF` bytes).
+pub(crate) fn prefix_successor(prefix: &[u8]) -> Option<Bytes> {
+    let mut successor = prefix.to_vec();
+
+    while let Some(last) = successor.pop() {
+        if last < u8::MAX {
+            successor.push(last + 1);
+            return Some(Bytes::from(successor));
+        }
+    }
+
+    None
+}
+
+#[test]
+fn smoke_prefix_successor() {
+    assert_eq!(prefix_successor(b""), None);
+    assert_eq!(prefix_successor(&[0xFF, 0xFF]), None);
+    assert_eq!(prefix_successor(b"ab"), Some(Bytes::from(&b"ac"[..])));
+    assert_eq!(prefix_successor(&[1, 0xFF]), Some(Bytes::from(&[2_u8][..])));
+}
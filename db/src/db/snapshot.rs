@@ -0,0 +1,127 @@
+use std::io;
+use std::ops::{Bound, RangeBounds};
+
+use super::collection::encode_key;
+use super::log::encode_seq;
+use super::range::prefix_successor;
+use super::storage::SnapshotLsn;
+use super::{Collection, Db, Log, LogIter, Range};
+use crate::object_store::{ObjectId, ObjectMetadata, decode_object_ref};
+use crate::util::block_on;
+use crate::{Bytes, CollectionId};
+
+/// A read-only, consistent view of the database as of a single commit.
+///
+/// A snapshot never observes commits that happen after it was taken, and
+/// reading from it does not block writers. Versions of data that are
+/// visible to a snapshot are retained until it is dropped.
+pub struct Snapshot<'a> {
+    pub(crate) db: &'a Db,
+    pub(crate) at: SnapshotLsn,
+}
+
+impl<'a> Snapshot<'a> {
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        self.get_in(&Collection::DEFAULT, key)
+    }
+
+    pub fn get_in(&self, collection: &Collection, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let storage_key = encode_key(collection.id, key);
+
+        Ok(self
+            .db
+            .storage
+            .get(&storage_key, self.at)?
+            .map(|value| value.inner.to_vec()))
+    }
+
+    /// Iterate over a range of keys in order. Supports reverse iteration
+    /// via [`DoubleEndedIterator`].
+    pub fn range<K, R>(&self, range: R) -> Range<'_>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        self.range_in(&Collection::DEFAULT, range)
+    }
+
+    pub fn range_in<K, R>(&self, collection: &Collection, range: R) -> Range<'_>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let to_bytes = |bound: Bound<&K>| bound.map(|k| Bytes::from(k.as_ref()));
+
+        self.new_range(
+            collection,
+            to_bytes(range.start_bound()),
+            to_bytes(range.end_bound()),
+        )
+    }
+
+    /// Iterate over all keys that begin with `prefix`, in order.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Range<'_> {
+        self.scan_prefix_in(&Collection::DEFAULT, prefix)
+    }
+
+    pub fn scan_prefix_in(&self, collection: &Collection, prefix: &[u8]) -> Range<'_> {
+        let hi = match prefix_successor(prefix) {
+            Some(successor) => Bound::Excluded(successor),
+            None => Bound::Unbounded,
+        };
+
+        self.new_range(collection, Bound::Included(Bytes::from(prefix)), hi)
+    }
+
+    /// Iterate over the entries of a log, starting at sequence number
+    /// `seq`.
+    pub fn read_from(&self, log: &Log, seq: u64) -> LogIter<'_> {
+        LogIter {
+            range: self.new_range(
+                &log.collection(),
+                Bound::Included(encode_seq(seq)),
+                Bound::Unbounded,
+            ),
+        }
+    }
+
+    /// Reads the bytes of an object.
+    pub fn get_object(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let Some((id, metadata)) = self.read_object_ref(key)? else {
+            return Ok(None);
+        };
+
+        block_on(self.db.objects.read(id, metadata)).map(Some)
+    }
+
+    pub fn object_metadata(&self, key: &[u8]) -> io::Result<Option<ObjectMetadata>> {
+        Ok(self.read_object_ref(key)?.map(|(_id, metadata)| metadata))
+    }
+
+    fn read_object_ref(&self, key: &[u8]) -> io::Result<Option<(ObjectId, ObjectMetadata)>> {
+        let storage_key = encode_key(CollectionId::OBJECTS, key);
+
+        Ok(self
+            .db
+            .storage
+            .get(&storage_key, self.at)?
+            .map(|value| decode_object_ref(&value)))
+    }
+
+    fn new_range(&self, collection: &Collection, lo: Bound<Bytes>, hi: Bound<Bytes>) -> Range<'_> {
+        Range {
+            db: self.db,
+            at: self.at,
+            collection: collection.id,
+            write_batch: None,
+            lo,
+            hi,
+        }
+    }
+}
+
+impl<'a> Drop for Snapshot<'a> {
+    fn drop(&mut self) {
+        self.db.release_snapshot(self.at);
+    }
+}
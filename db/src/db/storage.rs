@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::table::Table;
+use crate::{Bytes, Lsn, WriteBatch};
+
+/// A snapshot reads every version stamped at or before it. `None` is
+/// the empty database that exists before the first commit, which
+/// (conveniently) sorts before every `Some(lsn)`.
+pub(crate) type SnapshotLsn = Option<Lsn>;
+
+/// Committed data, keeping every version of a key that may still be
+/// visible to a live snapshot.
+///
+/// The versions of a batch are all inserted before its `Lsn` becomes
+/// the stable `Lsn` handed out to new snapshots, so readers never
+/// observe a partially-applied commit. Readers only take the versions
+/// lock for the duration of a single lookup or iterator step, so a
+/// long scan over a snapshot does not block commits.
+///
+/// Versions live in memory (the memtable) until they are flushed to an
+/// immutable [`Table`]. Reads consult the memtable first and then the
+/// tables from newest to oldest, stopping at the first version or
+/// tombstone found. Flushing only happens at or before the horizon, so
+/// every live snapshot sees the state that was flushed.
+#[derive(Default)]
+pub(crate) struct Storage {
+    versions: RwLock<Versions>,
+    snapshots: Mutex<Snapshots>,
+}
+
+#[derive(Default)]
+struct Versions {
+    /// Versions of each key, ordered from oldest to newest. A `None`
+    /// value is a tombstone.
+    map: BTreeMap<Bytes, Vec<(Lsn, Option<Bytes>)>>,
+    /// Keys which gained a version at `Lsn` while having older versions
+    /// (or a tombstone) that can be dropped once no snapshot at or
+    /// before that `Lsn` remains.
+    gc_queue: VecDeque<(Lsn, Bytes)>,
+    /// The approximate size of the keys and values in `map`.
+    memtable_bytes: usize,
+    /// Flushed tables, from newest to oldest.
+    tables: Vec<Arc<Table>>,
+}
+
+#[derive(Default)]
+struct Snapshots {
+    stable: SnapshotLsn,
+    live: BTreeMap<SnapshotLsn, usize>,
+}
+
+impl Snapshots {
+    fn horizon(&self) -> SnapshotLsn {
+        self.live.keys().next().copied().unwrap_or(self.stable)
+    }
+}
+
+/// The version of a key visible at `at`, which is `Some(None)` for a
+/// tombstone and `None` if every version is newer than `at`.
+fn visible(versions: &[(Lsn, Option<Bytes>)], at: SnapshotLsn) -> Option<&Option<Bytes>> {
+    versions
+        .iter()
+        .rev()
+        .find(|(lsn, _)| Some(*lsn) <= at)
+        .map(|(_, value_opt)| value_opt)
+}
+
+fn entry_bytes(key: &Bytes, value_opt: &Option<Bytes>) -> usize {
+    key.len() + value_opt.as_ref().map_or(0, |value| value.len())
+}
+
+impl Storage {
+    /// Storage whose flushed state through `flushed` is `tables`, ordered
+    /// from newest to oldest. Batches after `flushed` must be applied
+    /// before the storage is used.
+    pub(crate) fn recover(tables: Vec<Arc<Table>>, flushed: SnapshotLsn) -> Storage {
+        let storage = Storage::default();
+        storage.versions.write().unwrap().tables = tables;
+        storage.snapshots.lock().unwrap().stable = flushed;
+        storage
+    }
+
+    /// Registers a snapshot at the latest stable `Lsn`, preventing the
+    /// versions it can see from being garbage collected until it is
+    /// passed to [`Storage::unpin`].
+    pub(crate) fn pin(&self) -> SnapshotLsn {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let at = snapshots.stable;
+        *snapshots.live.entry(at).or_default() += 1;
+        at
+    }
+
+    pub(crate) fn unpin(&self, at: SnapshotLsn) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+
+        let count = snapshots
+            .live
+            .get_mut(&at)
+            .expect("unpinned a snapshot that was never pinned");
+
+        *count -= 1;
+
+        if *count == 0 {
+            snapshots.live.remove(&at);
+            drop(snapshots);
+            self.collect_garbage();
+        }
+    }
+
+    /// The oldest `Lsn` that any current or future snapshot may read at.
+    pub(crate) fn horizon(&self) -> SnapshotLsn {
+        self.snapshots.lock().unwrap().horizon()
+    }
+
+    /// The `Lsn` that the next commit should be stamped with. Only
+    /// meaningful while commits are serialized by the caller.
+    pub(crate) fn next_lsn(&self) -> Lsn {
+        match self.snapshots.lock().unwrap().stable {
+            Some(lsn) => lsn.next(),
+            None => Lsn::FIRST,
+        }
+    }
+
+    /// The `Lsn` of the most recent commit.
+    pub(crate) fn stable(&self) -> SnapshotLsn {
+        self.snapshots.lock().unwrap().stable
+    }
+
+    /// Reads the value of a key visible at `at`. Fails if a table that
+    /// may hold the key can't be read.
+    pub(crate) fn get(&self, key: &Bytes, at: SnapshotLsn) -> io::Result<Option<Bytes>> {
+        let tables = {
+            let versions = self.versions.read().unwrap();
+
+            if let Some(value_opt) = versions.map.get(key).and_then(|vs| visible(vs, at)) {
+                return Ok(value_opt.clone());
+            }
+
+            versions.tables.clone()
+        };
+
+        for table in tables {
+            if let Some(value_opt) = table.get(key)? {
+                return Ok(value_opt);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the most recently committed value of a key.
+    pub(crate) fn get_latest(&self, key: &Bytes) -> io::Result<Option<Bytes>> {
+        let at = self.snapshots.lock().unwrap().stable;
+        self.get(key, at)
+    }
+
+    /// Returns the lowest key within the bounds that has a visible value.
+    pub(crate) fn first_in(
+        &self,
+        (lo, hi): (Bound<&Bytes>, Bound<&Bytes>),
+        at: SnapshotLsn,
+    ) -> io::Result<Option<(Bytes, Bytes)>> {
+        let mut lo = lo.cloned();
+
+        loop {
+            // The lowest key in any source is the next candidate. Which
+            // version of it is visible is decided separately, because
+            // the memtable may only hold versions that are newer than
+            // `at`, or a tombstone shadowing a table.
+            let (mut candidate, tables) = {
+                let versions = self.versions.read().unwrap();
+                let memtable_first = versions
+                    .map
+                    .range::<Bytes, _>((lo.as_ref(), hi))
+                    .next()
+                    .map(|(k, _)| k.clone());
+                (memtable_first, versions.tables.clone())
+            };
+
+            for table in &tables {
+                if let Some((key, _)) = table.first_in((lo.as_ref(), hi))?
+                    && candidate.as_ref().is_none_or(|c| key < *c)
+                {
+                    candidate = Some(key);
+                }
+            }
+
+            let Some(key) = candidate else {
+                return Ok(None);
+            };
+
+            if let Some(value) = self.get(&key, at)? {
+                return Ok(Some((key, value)));
+            }
+
+            lo = Bound::Excluded(key);
+        }
+    }
+
+    /// Returns the highest key within the bounds that has a visible value.
+    pub(crate) fn last_in(
+        &self,
+        (lo, hi): (Bound<&Bytes>, Bound<&Bytes>),
+        at: SnapshotLsn,
+    ) -> io::Result<Option<(Bytes, Bytes)>> {
+        let mut hi = hi.cloned();
+
+        loop {
+            let (mut candidate, tables) = {
+                let versions = self.versions.read().unwrap();
+                let memtable_last = versions
+                    .map
+                    .range::<Bytes, _>((lo, hi.as_ref()))
+                    .next_back()
+                    .map(|(k, _)| k.clone());
+                (memtable_last, versions.tables.clone())
+            };
+
+            for table in &tables {
+                if let Some((key, _)) = table.last_in((lo, hi.as_ref()))?
+                    && candidate.as_ref().is_none_or(|c| key > *c)
+                {
+                    candidate = Some(key);
+                }
+            }
+
+            let Some(key) = candidate else {
+                return Ok(None);
+            };
+
+            if let Some(value) = self.get(&key, at)? {
+                return Ok(Some((key, value)));
+            }
+
+            hi = Bound::Excluded(key);
+        }
+    }
+
+    /// Flushed tables, from newest to oldest.
+    pub(crate) fn tables(&self) -> Vec<Arc<Table>> {
+        self.versions.read().unwrap().tables.clone()
+    }
+
+    /// Replaces adjacent tables, which were merged by compaction, with the
+    /// table that they were merged into.
+    pub(crate) fn replace_tables(&self, merged: &[Arc<Table>], output_opt: Option<Arc<Table>>) {
+        let mut versions = self.versions.write().unwrap();
+
+        let position = versions
+            .tables
+            .iter()
+            .position(|table| Arc::ptr_eq(table, &merged[0]))
+            .expect("merged table is missing");
+        versions
+            .tables
+            .retain(|table| !merged.iter().any(|m| Arc::ptr_eq(m, table)));
+        if let Some(output) = output_opt {
+            versions.tables.insert(position, output);
+        }
+    }
+
+    /// The approximate size of the versions held in memory.
+    pub(crate) fn memtable_bytes(&self) -> usize {
+        self.versions.read().unwrap().memtable_bytes
+    }
+
+    /// The newest version of every key at or before `through`, including
+    /// tombstones if they may shadow a version in a table.
+    pub(crate) fn memtable_through(&self, through: Lsn) -> Vec<(Bytes, Option<Bytes>)> {
+        let versions = self.versions.read().unwrap();
+        let keep_tombstones = !versions.tables.is_empty();
+
+        versions
+            .map
+            .iter()
+            .filter_map(|(key, key_versions)| {
+                let value_opt = visible(key_versions, Some(through))?;
+                if value_opt.is_none() && !keep_tombstones {
+                    return None;
+                }
+                Some((key.clone(), value_opt.clone()))
+            })
+            .collect()
+    }
+
+    /// Installs a table written from [`Storage::memtable_through`], and
+    /// drops the versions that it replaces from memory.
+    pub(crate) fn install_flushed(&self, table_opt: Option<Arc<Table>>, through: Lsn) {
+        let mut versions = self.versions.write().unwrap();
+
+        if let Some(table) = table_opt {
+            versions.tables.insert(0, table);
+        }
+
+        let mut memtable_bytes = 0;
+        versions.map.retain(|key, key_versions| {
+            key_versions.retain(|(lsn, _)| *lsn > through);
+            for (_, value_opt) in key_versions.iter() {
+                memtable_bytes += entry_bytes(key, value_opt);
+            }
+            !key_versions.is_empty()
+        });
+        versions.memtable_bytes = memtable_bytes;
+    }
+
+    /// Installs a batch as new versions, then publishes `lsn` as the
+    /// stable `Lsn` for new snapshots. Calls must be serialized and have
+    /// increasing `lsn`s.
+    pub(crate) fn apply(&self, lsn: Lsn, batch: &WriteBatch) {
+        let mut versions = self.versions.write().unwrap();
+
+        for (key, value_opt) in batch {
+            versions.memtable_bytes += entry_bytes(key, value_opt);
+
+            let key_versions = versions.map.entry(key.clone()).or_default();
+            key_versions.push((lsn, value_opt.clone()));
+
+            if key_versions.len() > 1 || value_opt.is_none() {
+                versions.gc_queue.push_back((lsn, key.clone()));
+            }
+        }
+
+        drop(versions);
+
+        self.snapshots.lock().unwrap().stable = Some(lsn);
+
+        self.collect_garbage();
+    }
+
+    /// Drops versions that are shadowed at the horizon, and tombstones
+    /// that no snapshot can see past. Tombstones are kept while tables
+    /// exist, as they may shadow versions in those tables.
+    fn collect_garbage(&self) {
+        let horizon = self.horizon();
+
+        let mut versions = self.versions.write().unwrap();
+        let keep_tombstones = !versions.tables.is_empty();
+
+        while let Some((lsn, _)) = versions.gc_queue.front() {
+            if Some(*lsn) > horizon {
+                break;
+            }
+
+            let (_, key) = versions.gc_queue.pop_front().unwrap();
+
+            let Some(key_versions) = versions.map.get_mut(&key) else {
+                continue;
+            };
+
+            let Some(newest_at_horizon) = key_versions
+                .iter()
+                .rposition(|(lsn, _)| Some(*lsn) <= horizon)
+            else {
+                continue;
+            };
+
+            let mut dropped_bytes: usize = key_versions
+                .drain(..newest_at_horizon)
+                .map(|(_, value_opt)| entry_bytes(&key, &value_opt))
+                .sum();
+
+            if !keep_tombstones && key_versions.len() == 1 && key_versions[0].1.is_none() {
+                versions.map.remove(&key);
+                dropped_bytes += key.len();
+            }
+
+            versions.memtable_bytes -= dropped_bytes;
+        }
+    }
+}
+
+#[test]
+fn smoke_storage_gc() {
+    let key = Bytes::from(&b"k"[..]);
+    let write = |value: Option<&[u8]>| {
+        let mut batch = WriteBatch::new();
+        batch.insert(key.clone(), value.map(Bytes::from));
+        batch
+    };
+
+    let storage = Storage::default();
+
+    let lsn_1 = storage.next_lsn();
+    storage.apply(lsn_1, &write(Some(b"1")));
+
+    let at_1 = storage.pin();
+
+    let lsn_2 = storage.next_lsn();
+    storage.apply(lsn_2, &write(None));
+
+    let lsn_3 = storage.next_lsn();
+    storage.apply(lsn_3, &write(Some(b"3")));
+
+    assert_eq!(
+        storage.get(&key, at_1).unwrap().unwrap(),
+        Bytes::from(&b"1"[..])
+    );
+    assert_eq!(storage.get(&key, Some(lsn_2)).unwrap(), None);
+    assert_eq!(storage.get(&key, None).unwrap(), None);
+    assert_eq!(storage.versions.read().unwrap().map[&key].len(), 3);
+
+    storage.unpin(at_1);
+
+    assert_eq!(storage.versions.read().unwrap().map[&key].len(), 1);
+    assert_eq!(storage.memtable_bytes(), 2);
+    assert_eq!(
+        storage.get(&key, Some(lsn_3)).unwrap().unwrap(),
+        Bytes::from(&b"3"[..])
+    );
+
+    let lsn_4 = storage.next_lsn();
+    storage.apply(lsn_4, &write(None));
+
+    assert!(storage.versions.read().unwrap().map.is_empty());
+    assert_eq!(storage.memtable_bytes(), 0);
+}
@@ -0,0 +1,568 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bloomfilter::Bloom;
+
+use super::cache::{BlockCache, BlockId};
+use crate::Bytes;
+use crate::fs::Fs;
+use crate::util::{
+    BytesBlock, Compression, FILE_HEADER_LEN, FileCipher, Keys, RecordType, check_file_header,
+    read_batch, read_frame, write_batch, write_file_header, write_frame,
+};
+
+const TARGET_BLOCK_BYTES: usize = 32 * 1024;
+const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.01;
+const BLOOM_SEED: [u8; 32] = *b"komora-io/db table bloom filter!";
+
+/// Values in data blocks are tagged, as a removed key is stored with a
+/// tombstone that shadows its versions in older tables.
+const INSERT: u8 = 1;
+const REMOVE: u8 = 2;
+
+/// Where a data block is stored within a table file.
+#[derive(Debug, Clone, Copy)]
+struct BlockHandle {
+    offset: u64,
+    len: u64,
+}
+
+pub(crate) fn table_path(directory: &Path, id: u64) -> PathBuf {
+    directory.join(format!("table.{id:016x}"))
+}
+
+/// An immutable, sorted table of storage keys written by a memtable
+/// flush or a compaction. Keys map to their newest value as of the
+/// flush, or to a tombstone.
+///
+/// After the file header, the file is a sequence of framed data blocks,
+/// each a [`BytesBlock`] of keys and tagged values, then a framed
+/// metadata section holding the key range, a bloom filter and the last
+/// key of every data block, and finally the offset of the metadata. Only
+/// the metadata is kept in memory, and data blocks are read on demand.
+///
+/// A table replaced by compaction is marked obsolete, and its file is
+/// deleted once the last reader drops it.
+pub(crate) struct Table {
+    fs: Arc<dyn Fs + Send + Sync>,
+    path: PathBuf,
+    pub(crate) id: u64,
+    pub(crate) len: u64,
+    /// Decrypts the frames of the file, as named by its header.
+    cipher: FileCipher,
+    min_key: Bytes,
+    max_key: Bytes,
+    bloom: Bloom<[u8]>,
+    /// The last key of each data block, in order.
+    index: Vec<(Bytes, BlockHandle)>,
+    obsolete: AtomicBool,
+    cache: Arc<BlockCache>,
+}
+
+impl Drop for Table {
+    fn drop(&mut self) {
+        if self.obsolete.load(Ordering::Acquire) {
+            // an undeleted file is removed during the next recovery
+            let _ = self.fs.delete(&self.path);
+            self.cache.remove_table(self.id);
+        }
+    }
+}
+
+fn decode_value(tagged: &[u8]) -> Option<Bytes> {
+    match tagged.split_first() {
+        Some((&INSERT, value)) => Some(Bytes::from(value)),
+        _ => None,
+    }
+}
+
+fn corrupt(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("table file {path:?} is corrupt"),
+    )
+}
+
+/// Writes a table one data block at a time, so that the entries of a
+/// table never need to be held in memory all at once. The file is
+/// created along with its first data block, and deleted again if the
+/// writer is dropped before [`TableWriter::finish`].
+pub(crate) struct TableWriter {
+    fs: Arc<dyn Fs + Send + Sync>,
+    cache: Arc<BlockCache>,
+    compression: Compression,
+    cipher: FileCipher,
+    path: PathBuf,
+    id: u64,
+    /// The length of the file so far, or `None` before it is created.
+    len: Option<u64>,
+    /// The tagged values of the data block being built.
+    block: BTreeMap<Bytes, Vec<u8>>,
+    block_bytes: usize,
+    min_key: Option<Bytes>,
+    bloom: Bloom<[u8]>,
+    index: Vec<(Bytes, BlockHandle)>,
+    finished: bool,
+}
+
+impl TableWriter {
+    /// Starts writing a table of at most `max_entries` entries,
+    /// compressing its blocks with `compression` and encrypting them with
+    /// the current key in `keys`.
+    pub(crate) fn new(
+        fs: Arc<dyn Fs + Send + Sync>,
+        cache: Arc<BlockCache>,
+        compression: Compression,
+        keys: &Keys,
+        directory: &Path,
+        id: u64,
+        max_entries: usize,
+    ) -> TableWriter {
+        let bloom = Bloom::new_for_fp_rate_with_seed(
+            max_entries.max(1),
+            BLOOM_FALSE_POSITIVE_RATE,
+            &BLOOM_SEED,
+        )
+        .unwrap();
+
+        TableWriter {
+            fs,
+            cache,
+            compression,
+            cipher: keys.for_writing(),
+            path: table_path(directory, id),
+            id,
+            len: None,
+            block: BTreeMap::new(),
+            block_bytes: 0,
+            min_key: None,
+            bloom,
+            index: vec![],
+            finished: false,
+        }
+    }
+
+    /// Adds an entry, whose key must be greater than that of every entry
+    /// added before it.
+    pub(crate) fn push(&mut self, key: Bytes, value_opt: Option<Bytes>) -> io::Result<()> {
+        let tagged = match value_opt {
+            Some(value) => [&[INSERT][..], &value].concat(),
+            None => vec![REMOVE],
+        };
+
+        self.bloom.set(&key[..]);
+        self.min_key.get_or_insert_with(|| key.clone());
+        self.block_bytes += key.len() + tagged.len();
+        self.block.insert(key, tagged);
+
+        if self.block_bytes >= TARGET_BLOCK_BYTES {
+            self.write_block()?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends the data block being built to the file, creating the file
+    /// if this is its first block.
+    fn write_block(&mut self) -> io::Result<()> {
+        let Some((last_key, _)) = self.block.last_key_value() else {
+            return Ok(());
+        };
+        let last_key = last_key.clone();
+
+        let mut buf = vec![];
+        if self.len.is_none() {
+            write_file_header(&self.cipher, &mut buf)?;
+        }
+
+        let block = BytesBlock::from(&self.block);
+        let offset = self.len.unwrap_or(0) + buf.len() as u64;
+        write_frame(
+            RecordType::TableBlock,
+            self.compression,
+            &self.cipher,
+            offset,
+            block.as_bytes(),
+            &mut buf,
+        )?;
+        let len = self.len.unwrap_or(0) + buf.len() as u64 - offset;
+
+        self.append(buf)?;
+
+        self.index.push((last_key, BlockHandle { offset, len }));
+        self.block.clear();
+        self.block_bytes = 0;
+
+        Ok(())
+    }
+
+    fn append(&mut self, buf: Vec<u8>) -> io::Result<()> {
+        match self.len {
+            None => self.fs.create_unique(&self.path, &buf)?,
+            Some(_) => self.fs.append(&self.path, &buf)?,
+        }
+        *self.len.get_or_insert(0) += buf.len() as u64;
+
+        Ok(())
+    }
+
+    /// Writes the metadata of the table and makes it durable, returning
+    /// `None` if no entry was added.
+    pub(crate) fn finish(mut self) -> io::Result<Option<Table>> {
+        self.write_block()?;
+
+        let Some(meta_offset) = self.len else {
+            self.finished = true;
+            return Ok(None);
+        };
+
+        let mut handles = vec![];
+        for (_last_key, handle) in &self.index {
+            let mut handle_bytes = handle.offset.to_le_bytes().to_vec();
+            handle_bytes.extend_from_slice(&handle.len.to_le_bytes());
+            handles.push(handle_bytes);
+        }
+
+        let min_key = self.min_key.take().unwrap();
+        let max_key = self.index.last().unwrap().0.clone();
+        let bloom_bytes = self.bloom.to_bytes();
+
+        let mut meta: Vec<&[u8]> = vec![&min_key, &max_key, &bloom_bytes];
+        for ((last_key, _handle), handle_bytes) in self.index.iter().zip(&handles) {
+            meta.push(last_key);
+            meta.push(handle_bytes);
+        }
+
+        let mut buf = vec![];
+        write_batch(
+            RecordType::TableMeta,
+            self.compression,
+            &self.cipher,
+            meta_offset,
+            meta.into_iter(),
+            &mut buf,
+        )?;
+        buf.extend_from_slice(&meta_offset.to_le_bytes());
+
+        self.append(buf)?;
+        self.fs.sync(&self.path)?;
+        self.finished = true;
+
+        Ok(Some(Table {
+            fs: self.fs.clone(),
+            path: self.path.clone(),
+            id: self.id,
+            len: self.len.unwrap(),
+            cipher: self.cipher.clone(),
+            min_key,
+            max_key,
+            // the writer is dropped without the bloom filter it built
+            bloom: std::mem::replace(
+                &mut self.bloom,
+                Bloom::new_with_seed(1, 1, &BLOOM_SEED).unwrap(),
+            ),
+            index: std::mem::take(&mut self.index),
+            obsolete: AtomicBool::new(false),
+            cache: self.cache.clone(),
+        }))
+    }
+}
+
+impl Drop for TableWriter {
+    fn drop(&mut self) {
+        if !self.finished && self.len.is_some() {
+            // an undeleted file is removed during the next recovery
+            let _ = self.fs.delete(&self.path);
+        }
+    }
+}
+
+impl Table {
+    /// Writes a table holding `entries`, which must be sorted by key and
+    /// non-empty, compressing its blocks with `compression` and encrypting
+    /// them with the current key in `keys`.
+    pub(crate) fn write(
+        fs: Arc<dyn Fs + Send + Sync>,
+        cache: Arc<BlockCache>,
+        compression: Compression,
+        keys: &Keys,
+        directory: &Path,
+        id: u64,
+        entries: &[(Bytes, Option<Bytes>)],
+    ) -> io::Result<Table> {
+        assert!(!entries.is_empty());
+
+        let mut writer =
+            TableWriter::new(fs, cache, compression, keys, directory, id, entries.len());
+        for (key, value_opt) in entries {
+            writer.push(key.clone(), value_opt.clone())?;
+        }
+
+        Ok(writer.finish()?.unwrap())
+    }
+
+    /// Opens a table file of length `len`, reading its metadata.
+    pub(crate) fn open(
+        fs: Arc<dyn Fs + Send + Sync>,
+        cache: Arc<BlockCache>,
+        keys: &Keys,
+        directory: &Path,
+        id: u64,
+        len: u64,
+    ) -> io::Result<Table> {
+        let path = table_path(directory, id);
+
+        let mut header = [0; FILE_HEADER_LEN];
+        fs.read_at_exact(&path, 0, &mut header)?;
+        let cipher = keys.for_file(check_file_header(&header, "table")?)?;
+
+        let mut footer = [0; 8];
+        let footer_offset = len.checked_sub(8).ok_or_else(|| corrupt(&path))?;
+        fs.read_at_exact(&path, footer_offset as usize, &mut footer)?;
+
+        let meta_offset = u64::from_le_bytes(footer);
+        let meta_len = footer_offset
+            .checked_sub(meta_offset)
+            .ok_or_else(|| corrupt(&path))?;
+
+        let mut meta_buf = vec![0; meta_len as usize];
+        fs.read_at_exact(&path, meta_offset as usize, &mut meta_buf)?;
+
+        let meta = read_batch(
+            RecordType::TableMeta,
+            &cipher,
+            meta_offset,
+            &mut &meta_buf[..],
+        )?;
+        if meta.len() < 3 || meta.len() % 2 != 1 {
+            return Err(corrupt(&path));
+        }
+
+        let mut meta = meta.into_iter();
+        let min_key = Bytes::from(meta.next().unwrap());
+        let max_key = Bytes::from(meta.next().unwrap());
+        let bloom = Bloom::from_bytes(meta.next().unwrap()).map_err(|_| corrupt(&path))?;
+
+        let mut index = vec![];
+        while let (Some(last_key), Some(handle_bytes)) = (meta.next(), meta.next()) {
+            if handle_bytes.len() != 16 {
+                return Err(corrupt(&path));
+            }
+            let (offset, len) = handle_bytes.split_at(8);
+            index.push((
+                Bytes::from(last_key),
+                BlockHandle {
+                    offset: u64::from_le_bytes(offset.try_into().unwrap()),
+                    len: u64::from_le_bytes(len.try_into().unwrap()),
+                },
+            ));
+        }
+
+        Ok(Table {
+            fs,
+            path,
+            id,
+            len,
+            cipher,
+            min_key,
+            max_key,
+            bloom,
+            index,
+            obsolete: AtomicBool::new(false),
+            cache,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Deletes the file of this table once it is dropped.
+    pub(crate) fn mark_obsolete(&self) {
+        self.obsolete.store(true, Ordering::Release);
+    }
+
+    /// The number of entries that the bloom filter of the table was sized
+    /// for, which is at least the number of entries in the table.
+    pub(crate) fn bloom_capacity(&self) -> usize {
+        let ln2 = std::f64::consts::LN_2;
+        let bits = self.bloom.len() as f64;
+
+        (bits * ln2 * ln2 / -BLOOM_FALSE_POSITIVE_RATE.ln()).ceil() as usize
+    }
+
+    pub(crate) fn block_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// The length of a data block within the file.
+    pub(crate) fn block_len(&self, block_index: usize) -> u64 {
+        self.index[block_index].1.len
+    }
+
+    /// Reads every entry of a data block, in order.
+    pub(crate) fn read_block_entries(
+        &self,
+        block_index: usize,
+    ) -> io::Result<Vec<(Bytes, Option<Bytes>)>> {
+        let block = self.read_block(block_index)?;
+
+        Ok(block
+            .iter()
+            .map(|(key, tagged)| (Bytes::from(key), decode_value(tagged)))
+            .collect())
+    }
+
+    /// Reads a data block through the block cache.
+    fn read_block(&self, block_index: usize) -> io::Result<Arc<BytesBlock>> {
+        let id = BlockId {
+            table_id: self.id,
+            block_index,
+        };
+
+        self.cache.get_or_read(id, || {
+            let handle = self.index[block_index].1;
+            let mut buf = vec![0; handle.len as usize];
+            self.fs
+                .read_at_exact(&self.path, handle.offset as usize, &mut buf)?;
+
+            BytesBlock::from_bytes(read_frame(
+                RecordType::TableBlock,
+                &self.cipher,
+                handle.offset,
+                &mut &buf[..],
+            )?)
+        })
+    }
+
+    /// Looks up a key, returning `None` if this table has no entry for
+    /// it and `Some(None)` if it has a tombstone.
+    pub(crate) fn get(&self, key: &Bytes) -> io::Result<Option<Option<Bytes>>> {
+        if *key < self.min_key || *key > self.max_key || !self.bloom.check(&key[..]) {
+            return Ok(None);
+        }
+
+        let block_index = self.index.partition_point(|(last_key, _)| last_key < key);
+        if block_index >= self.index.len() {
+            return Ok(None);
+        }
+
+        let block = self.read_block(block_index)?;
+
+        Ok(block.get(&key[..]).map(decode_value))
+    }
+
+    /// Returns the entry with the lowest key within the bounds.
+    pub(crate) fn first_in(
+        &self,
+        (lo, hi): (Bound<&Bytes>, Bound<&Bytes>),
+    ) -> io::Result<Option<(Bytes, Option<Bytes>)>> {
+        // the first block whose last key is within the lower bound
+        let block_index = self.index.partition_point(|(last_key, _)| match lo {
+            Bound::Included(lo) => last_key < lo,
+            Bound::Excluded(lo) => last_key <= lo,
+            Bound::Unbounded => false,
+        });
+
+        if block_index >= self.index.len() {
+            return Ok(None);
+        }
+
+        let block = self.read_block(block_index)?;
+
+        Ok(block
+            .range::<Bytes, _>((lo, hi))
+            .next()
+            .map(|(key, tagged)| (Bytes::from(key), decode_value(tagged))))
+    }
+
+    /// Returns the entry with the highest key within the bounds.
+    pub(crate) fn last_in(
+        &self,
+        (lo, hi): (Bound<&Bytes>, Bound<&Bytes>),
+    ) -> io::Result<Option<(Bytes, Option<Bytes>)>> {
+        // the first block whose last key reaches the upper bound, which
+        // is the only block that may hold keys on both sides of it
+        let block_index = self.index.partition_point(|(last_key, _)| match hi {
+            Bound::Included(hi) => last_key < hi,
+            Bound::Excluded(hi) => last_key < hi,
+            Bound::Unbounded => true,
+        });
+
+        let candidates = block_index.saturating_sub(1)..=block_index.min(self.index.len() - 1);
+
+        for block_index in candidates.rev() {
+            let block = self.read_block(block_index)?;
+
+            if let Some((key, tagged)) = block.range::<Bytes, _>((lo, hi)).next_back() {
+                return Ok(Some((Bytes::from(key), decode_value(tagged))));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[test]
+fn smoke_table() {
+    use crate::fs::MemFs;
+
+    let fs: Arc<dyn Fs + Send + Sync> = Arc::new(MemFs::default());
+    let directory = Path::new("tables");
+
+    // enough entries to span several data blocks
+    let entries: Vec<(Bytes, Option<Bytes>)> = (0_u32..10_000)
+        .map(|i| {
+            let key = Bytes::from(&(i * 2).to_be_bytes()[..]);
+            let value_opt = (i % 3 != 0).then(|| Bytes::from(vec![i as u8; i as usize % 17]));
+            (key, value_opt)
+        })
+        .collect();
+
+    let cache = Arc::new(BlockCache::new(1024 * 1024));
+
+    let written = Table::write(
+        fs.clone(),
+        cache.clone(),
+        Compression::None,
+        &Keys::default(),
+        directory,
+        7,
+        &entries,
+    )
+    .unwrap();
+    assert!(written.index.len() > 1);
+
+    let table = Table::open(fs, cache, &Keys::default(), directory, 7, written.len).unwrap();
+
+    for (key, value_opt) in &entries {
+        assert_eq!(table.get(key).unwrap(), Some(value_opt.clone()));
+    }
+
+    let missing = Bytes::from(&3_u32.to_be_bytes()[..]);
+    assert_eq!(table.get(&missing).unwrap(), None);
+
+    let (first, _) = table
+        .first_in((Bound::Excluded(&missing), Bound::Unbounded))
+        .unwrap()
+        .unwrap();
+    assert_eq!(&*first, &4_u32.to_be_bytes());
+
+    let (last, _) = table
+        .last_in((Bound::Unbounded, Bound::Excluded(&missing)))
+        .unwrap()
+        .unwrap();
+    assert_eq!(&*last, &2_u32.to_be_bytes());
+
+    let end = entries.last().unwrap().0.clone();
+    assert!(
+        table
+            .first_in((Bound::Excluded(&end), Bound::Unbounded))
+            .unwrap()
+            .is_none()
+    );
+}
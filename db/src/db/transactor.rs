@@ -1,7 +1,13 @@
+use std::error::Error;
+use std::io;
 use std::ops::{Bound, RangeBounds};
 
-use crate::util::{Bytes, WriteBatch};
+use super::{Collection, Snapshot};
 use crate::CollectionId;
+use crate::util::{Bytes, WriteBatch};
+
+/// The result of a transactor. An `Err` rejects the commit.
+pub type TransactorResult = Result<WriteBatch, Box<dyn Error + Send + Sync>>;
 
 pub enum InterestFilter {
     Key(Bytes),
@@ -12,7 +18,7 @@ pub enum InterestFilter {
 }
 
 impl InterestFilter {
-    fn matches(&self, input: &WriteBatch) -> bool {
+    pub(crate) fn matches(&self, input: &WriteBatch) -> bool {
         match self {
             InterestFilter::Key(match_key) => input.keys().any(|k| k == match_key),
             InterestFilter::Range { start, end } => input
@@ -22,18 +28,109 @@ impl InterestFilter {
     }
 }
 
+type Transformer = dyn Fn(&TransactorContext<'_>, WriteBatch) -> TransactorResult + Send + Sync;
+
+/// Returned from [`Tx::commit`](super::Tx::commit) when a transactor
+/// rejected the transaction's writes. The transaction had no effect.
+#[derive(Debug)]
+pub struct Rejected {
+    /// The name of the transactor that rejected the commit.
+    pub transactor: String,
+    /// The error returned by the transactor.
+    pub reason: Box<dyn Error + Send + Sync>,
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "transactor {:?} rejected the commit: {}",
+            self.transactor, self.reason
+        )
+    }
+}
+
+impl Error for Rejected {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.reason)
+    }
+}
+
+/// Read access to the database for a running transactor.
+///
+/// Reads observe the latest committed state, which cannot change while
+/// transactors run because commits are serialized. They do not observe
+/// the writes of the commit being transformed, which are passed to the
+/// transactor as its input.
+pub struct TransactorContext<'a> {
+    pub(crate) snapshot: &'a Snapshot<'a>,
+    pub(crate) collection: Collection,
+}
+
+impl<'a> TransactorContext<'a> {
+    /// The collection that the transactor is registered on.
+    pub fn collection(&self) -> &Collection {
+        &self.collection
+    }
+
+    /// Reads a key from the transactor's collection.
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        self.snapshot.get_in(&self.collection, key)
+    }
+
+    /// The latest committed state of every collection.
+    pub fn snapshot(&self) -> &Snapshot<'a> {
+        self.snapshot
+    }
+}
+
+/// Transforms the writes that a commit makes to a collection before
+/// they are logged, for example to maintain derived data in the same
+/// atomic commit or to enforce invariants by rejecting the commit.
+/// Registered with
+/// [`Db::register_transactor`](super::Db::register_transactor).
 pub struct Transactor {
-    collection_id: CollectionId,
+    pub(crate) name: String,
+    /// Transactors of a collection are applied in ascending position,
+    /// which is assigned when a name is first registered.
+    pub(crate) position: u64,
+    pub(crate) collection_id: CollectionId,
     filter: InterestFilter,
-    transformer: Box<dyn Fn(WriteBatch) -> WriteBatch>,
+    transformer: Box<Transformer>,
 }
 
 impl Transactor {
-    pub fn apply(&self, input: WriteBatch) -> WriteBatch {
+    pub(crate) fn new<F>(
+        name: &str,
+        position: u64,
+        collection_id: CollectionId,
+        filter: InterestFilter,
+        transformer: F,
+    ) -> Transactor
+    where
+        F: 'static + Fn(&TransactorContext<'_>, WriteBatch) -> TransactorResult + Send + Sync,
+    {
+        Transactor {
+            name: name.to_owned(),
+            position,
+            collection_id,
+            filter,
+            transformer: Box::new(transformer),
+        }
+    }
+
+    pub fn apply(
+        &self,
+        context: &TransactorContext<'_>,
+        input: WriteBatch,
+    ) -> Result<WriteBatch, Rejected> {
         if self.filter.matches(&input) {
-            (self.transformer)(input)
+            (self.transformer)(context, input).map_err(|reason| Rejected {
+                transactor: self.name.clone(),
+                reason,
+            })
         } else {
-            input
+            Ok(input)
         }
     }
 }
@@ -1,77 +1,455 @@
+use std::collections::BTreeMap;
 use std::io;
+use std::ops::{Bound, RangeBounds};
 
-use super::{Db, Range};
-use crate::{Bytes, WriteBatch};
+use terrors::OneOf;
 
+use super::collection::{
+    catalog_id_key, catalog_log_name_key, catalog_name_key, catalog_name_prefix,
+    catalog_next_id_key, catalog_next_transactor_position_key, catalog_transactor_key, decode_id,
+    encode_bounds, encode_key,
+};
+use super::log::encode_seq;
+use super::range::prefix_successor;
+use super::{Collection, Conflict, Log, LogIter, Range, ReadSet, Rejected, Snapshot};
+use crate::object_store::{ObjectId, ObjectMetadata, decode_object_ref};
+use crate::util::block_on;
+use crate::{Bytes, CollectionId, WriteBatch};
+
+/// A serializable transaction.
+///
+/// A transaction reads from the snapshot taken when it began.
+/// Everything it reads from storage is recorded, and [`Tx::commit`]
+/// fails with [`Conflict`] if any of it was modified by a transaction
+/// that committed after that snapshot.
+///
+/// The plain methods operate on the default collection, and the `_in`
+/// methods operate on a named [`Collection`]. Writes to every collection
+/// and appends to every [`Log`] are committed atomically, along with
+/// the objects put or deleted by the transaction.
 pub struct Tx<'a> {
-    pub(crate) db: &'a Db,
-    pub(crate) write_batch: WriteBatch,
+    pub(crate) snapshot: Snapshot<'a>,
+    pub(crate) read_set: ReadSet,
+    pub(crate) write_batches: BTreeMap<CollectionId, WriteBatch>,
+    pub(crate) log_appends: BTreeMap<CollectionId, Vec<Bytes>>,
+    /// The bytes of objects to put, or `None` for objects to delete.
+    pub(crate) object_writes: BTreeMap<Bytes, Option<Bytes>>,
 }
 
 impl<'a> Tx<'a> {
     pub fn get(&'_ mut self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
-        // First check if the key exists in the write batch
+        self.get_in(&Collection::DEFAULT, key)
+    }
+
+    pub fn insert(&'_ mut self, key: &[u8], value: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        self.insert_in(&Collection::DEFAULT, key, value)
+    }
+
+    pub fn remove(&'_ mut self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        self.remove_in(&Collection::DEFAULT, key)
+    }
+
+    /// Iterate over a range of keys in order, including any writes made
+    /// earlier in this transaction. Supports reverse iteration via
+    /// [`DoubleEndedIterator`].
+    pub fn range<K, R>(&'_ mut self, range: R) -> Range<'_>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        self.range_in(&Collection::DEFAULT, range)
+    }
+
+    /// Iterate over all keys that begin with `prefix`, in order.
+    pub fn scan_prefix(&'_ mut self, prefix: &[u8]) -> Range<'_> {
+        self.scan_prefix_in(&Collection::DEFAULT, prefix)
+    }
+
+    pub fn get_in(
+        &'_ mut self,
+        collection: &Collection,
+        key: &[u8],
+    ) -> io::Result<Option<Vec<u8>>> {
+        let key_bytes = Bytes::from(key);
+
+        Ok(self
+            .read(collection, &key_bytes)?
+            .map(|value| value.inner.to_vec()))
+    }
+
+    pub fn insert_in(
+        &'_ mut self,
+        collection: &Collection,
+        key: &[u8],
+        value: &[u8],
+    ) -> io::Result<Option<Vec<u8>>> {
         let key_bytes = Bytes::from(key);
-        if let Some(value) = self.write_batch.get(&key_bytes) {
-            return Ok(Some(value.inner.to_vec()));
+        let value_bytes = Bytes::from(value);
+
+        let old_value = self.read(collection, &key_bytes)?.map(|v| v.inner.to_vec());
+
+        self.write(collection, key_bytes, Some(value_bytes));
+
+        Ok(old_value)
+    }
+
+    pub fn remove_in(
+        &'_ mut self,
+        collection: &Collection,
+        key: &[u8],
+    ) -> io::Result<Option<Vec<u8>>> {
+        let key_bytes = Bytes::from(key);
+
+        let old_value = self.read(collection, &key_bytes)?.map(|v| v.inner.to_vec());
+
+        // Record a tombstone so that the removal shadows storage for the
+        // rest of this transaction and is applied on commit
+        self.write(collection, key_bytes, None);
+
+        Ok(old_value)
+    }
+
+    pub fn range_in<K, R>(&'_ mut self, collection: &Collection, range: R) -> Range<'_>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let to_bytes = |bound: Bound<&K>| bound.map(|k| Bytes::from(k.as_ref()));
+
+        self.new_range(
+            collection,
+            to_bytes(range.start_bound()),
+            to_bytes(range.end_bound()),
+        )
+    }
+
+    pub fn scan_prefix_in(&'_ mut self, collection: &Collection, prefix: &[u8]) -> Range<'_> {
+        let hi = match prefix_successor(prefix) {
+            Some(successor) => Bound::Excluded(successor),
+            None => Bound::Unbounded,
+        };
+
+        self.new_range(collection, Bound::Included(Bytes::from(prefix)), hi)
+    }
+
+    /// Append an entry to a log. The entry is assigned the log's next
+    /// sequence number when this transaction commits, and is not visible
+    /// to [`Tx::read_from`] before then.
+    pub fn append(&'_ mut self, log: &Log, entry: &[u8]) {
+        self.log_appends
+            .entry(log.id)
+            .or_default()
+            .push(Bytes::from(entry));
+    }
+
+    /// Iterate over the committed entries of a log, starting at sequence
+    /// number `seq`.
+    pub fn read_from(&'_ mut self, log: &Log, seq: u64) -> LogIter<'_> {
+        LogIter {
+            range: self.new_range(
+                &log.collection(),
+                Bound::Included(encode_seq(seq)),
+                Bound::Unbounded,
+            ),
         }
+    }
+
+    /// Remove every entry of a log with a sequence number lower than
+    /// `seq`. Later appends continue from the same sequence number as
+    /// before.
+    pub fn truncate_log(&'_ mut self, log: &Log, seq: u64) -> io::Result<()> {
+        let collection = log.collection();
+
+        let truncated = self
+            .new_range(
+                &collection,
+                Bound::Unbounded,
+                Bound::Excluded(encode_seq(seq)),
+            )
+            .map(|entry| entry.map(|(key, _entry)| key))
+            .collect::<io::Result<Vec<Bytes>>>()?;
 
-        // Otherwise check in the storage
-        let storage = self.db.storage.lock().unwrap();
-        if let Some(value) = storage.get(&key_bytes) {
-            return Ok(Some(value.inner.to_vec()));
+        for key in truncated {
+            self.write(&collection, key, None);
         }
 
-        // Key not found
-        Ok(None)
+        Ok(())
     }
 
-    pub fn insert(&'_ mut self, key: &[u8], value: &[u8]) -> Option<Vec<u8>> {
-        let key_bytes = Bytes::from(key);
-        let value_bytes = Bytes::from(value);
+    /// Store an object under `key`, replacing any previous object. The
+    /// bytes are written to a blob of their own when this transaction
+    /// commits, before the object becomes visible.
+    pub fn put_object(&'_ mut self, key: &[u8], bytes: &[u8]) {
+        self.object_writes
+            .insert(Bytes::from(key), Some(Bytes::from(bytes)));
+    }
 
-        // Check if the key already exists in storage or write batch
-        let old_value = {
-            if let Some(value) = self.write_batch.get(&key_bytes) {
-                Some(value.inner.to_vec())
-            } else {
-                let storage = self.db.storage.lock().unwrap();
-                storage.get(&key_bytes).map(|v| v.inner.to_vec())
-            }
+    /// Reads the bytes of an object.
+    pub fn get_object(&'_ mut self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        if let Some(pending) = self.object_writes.get(&Bytes::from(key)) {
+            return Ok(pending.as_ref().map(|bytes| bytes.to_vec()));
+        }
+
+        let Some((id, metadata)) = self.read_object_ref(key)? else {
+            return Ok(None);
         };
 
-        // Add to write batch
-        self.write_batch.insert(key_bytes, value_bytes);
+        block_on(self.snapshot.db.objects.read(id, metadata)).map(Some)
+    }
+
+    pub fn object_metadata(&'_ mut self, key: &[u8]) -> io::Result<Option<ObjectMetadata>> {
+        if let Some(pending) = self.object_writes.get(&Bytes::from(key)) {
+            return Ok(pending
+                .as_ref()
+                .map(|bytes| ObjectMetadata::for_bytes(bytes)));
+        }
 
-        old_value
+        Ok(self.read_object_ref(key)?.map(|(_id, metadata)| metadata))
     }
 
-    pub fn remove(&'_ mut self, key: &[u8]) -> Option<Vec<u8>> {
-        let key_bytes = Bytes::from(key);
+    /// Delete an object, returning `false` if it did not exist.
+    pub fn delete_object(&'_ mut self, key: &[u8]) -> io::Result<bool> {
+        let existed = self.object_metadata(key)?.is_some();
 
-        // Check if the key exists in storage or write batch
-        let old_value = {
-            if let Some(value) = self.write_batch.get(&key_bytes) {
-                Some(value.inner.to_vec())
-            } else {
-                let storage = self.db.storage.lock().unwrap();
-                storage.get(&key_bytes).map(|v| v.inner.to_vec())
-            }
+        self.object_writes.insert(Bytes::from(key), None);
+
+        Ok(existed)
+    }
+
+    fn read_object_ref(&mut self, key: &[u8]) -> io::Result<Option<(ObjectId, ObjectMetadata)>> {
+        Ok(self
+            .read(&Collection::OBJECTS, &Bytes::from(key))?
+            .map(|value| decode_object_ref(&value)))
+    }
+
+    /// Reads a key as seen by this transaction: pending writes (including
+    /// removals) in the write batch take precedence over storage.
+    fn read(&mut self, collection: &Collection, key: &Bytes) -> io::Result<Option<Bytes>> {
+        let pending = self
+            .write_batches
+            .get(&collection.id)
+            .and_then(|write_batch| write_batch.get(key));
+
+        if let Some(pending) = pending {
+            return Ok(pending.clone());
+        }
+
+        let storage_key = encode_key(collection.id, key);
+
+        self.read_set.record_key(&storage_key);
+
+        self.snapshot.db.storage.get(&storage_key, self.snapshot.at)
+    }
+
+    fn write(&mut self, collection: &Collection, key: Bytes, value_opt: Option<Bytes>) {
+        self.write_batches
+            .entry(collection.id)
+            .or_default()
+            .insert(key, value_opt);
+    }
+
+    fn new_range(
+        &'_ mut self,
+        collection: &Collection,
+        lo: Bound<Bytes>,
+        hi: Bound<Bytes>,
+    ) -> Range<'_> {
+        let (storage_lo, storage_hi) = encode_bounds(collection.id, lo.as_ref(), hi.as_ref());
+        self.read_set.record_range(&storage_lo, &storage_hi);
+
+        Range {
+            db: self.snapshot.db,
+            at: self.snapshot.at,
+            collection: collection.id,
+            write_batch: self.write_batches.get(&collection.id),
+            lo,
+            hi,
+        }
+    }
+
+    pub(crate) fn lookup_collection(&mut self, name: &str) -> io::Result<Option<Collection>> {
+        let value_opt = self.read(&Collection::CATALOG, &catalog_name_key(name))?;
+
+        Ok(value_opt.map(|value| Collection {
+            id: decode_id(&value),
+        }))
+    }
+
+    pub(crate) fn create_collection(&mut self, name: &str) -> io::Result<Collection> {
+        if let Some(collection) = self.lookup_collection(name)? {
+            return Ok(collection);
+        }
+
+        let id = self.allocate_id(catalog_name_key(name), name)?;
+
+        Ok(Collection { id })
+    }
+
+    pub(crate) fn create_log(&mut self, name: &str) -> io::Result<Log> {
+        let name_key = catalog_log_name_key(name);
+
+        if let Some(value) = self.read(&Collection::CATALOG, &name_key)? {
+            return Ok(Log {
+                id: decode_id(&value),
+            });
+        }
+
+        let id = self.allocate_id(name_key, name)?;
+
+        Ok(Log { id })
+    }
+
+    /// Assigns a never-before-used id to `name` in the catalog.
+    fn allocate_id(&mut self, name_key: Bytes, name: &str) -> io::Result<CollectionId> {
+        let next_id_key = catalog_next_id_key();
+        let id = self
+            .read(&Collection::CATALOG, &next_id_key)?
+            .map(|value| decode_id(&value))
+            .unwrap_or(CollectionId::FIRST_NAMED);
+
+        let id_bytes = Bytes::from(&id.to_be_bytes()[..]);
+        let next_id_bytes = Bytes::from(&id.next().to_be_bytes()[..]);
+
+        self.write(&Collection::CATALOG, next_id_key, Some(next_id_bytes));
+        self.write(&Collection::CATALOG, name_key, Some(id_bytes));
+        self.write(
+            &Collection::CATALOG,
+            catalog_id_key(id),
+            Some(Bytes::from(name.as_bytes())),
+        );
+
+        Ok(id)
+    }
+
+    /// The position of a transactor, which is assigned the first time its
+    /// name is registered.
+    pub(crate) fn transactor_position(&mut self, name: &str) -> io::Result<u64> {
+        let decode = |value: Bytes| u64::from_be_bytes((*value).try_into().unwrap());
+
+        let key = catalog_transactor_key(name);
+        if let Some(value) = self.read(&Collection::CATALOG, &key)? {
+            return Ok(decode(value));
+        }
+
+        let next_key = catalog_next_transactor_position_key();
+        let position = self
+            .read(&Collection::CATALOG, &next_key)?
+            .map_or(0, decode);
+
+        let next_bytes = Bytes::from(&(position + 1).to_be_bytes()[..]);
+        let position_bytes = Bytes::from(&position.to_be_bytes()[..]);
+
+        self.write(&Collection::CATALOG, next_key, Some(next_bytes));
+        self.write(&Collection::CATALOG, key, Some(position_bytes));
+
+        Ok(position)
+    }
+
+    pub(crate) fn forget_transactor(&mut self, name: &str) {
+        self.write(&Collection::CATALOG, catalog_transactor_key(name), None);
+    }
+
+    /// Removes a collection from the catalog along with all of its data.
+    pub(crate) fn drop_collection(&mut self, name: &str) -> io::Result<bool> {
+        let Some(collection) = self.lookup_collection(name)? else {
+            return Ok(false);
         };
 
-        // Remove from storage in the next commit by setting to None
-        // Note: In a real implementation you might handle this differently
-        self.write_batch.remove(&key_bytes);
+        self.write(&Collection::CATALOG, catalog_name_key(name), None);
+        self.write(&Collection::CATALOG, catalog_id_key(collection.id), None);
+
+        let keys = self
+            .range_in::<&[u8], _>(&collection, ..)
+            .map(|entry| entry.map(|(key, _value)| key))
+            .collect::<io::Result<Vec<Bytes>>>()?;
+
+        for key in keys {
+            self.write(&collection, key, None);
+        }
+
+        Ok(true)
+    }
+
+    pub(crate) fn rename_collection(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let Some(collection) = self.lookup_collection(from)? else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("collection {from:?} does not exist"),
+            ));
+        };
+
+        if from == to {
+            return Ok(());
+        }
+
+        if self.lookup_collection(to)?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("collection {to:?} already exists"),
+            ));
+        }
+
+        let id_bytes = Bytes::from(&collection.id.to_be_bytes()[..]);
+
+        self.write(&Collection::CATALOG, catalog_name_key(from), None);
+        self.write(&Collection::CATALOG, catalog_name_key(to), Some(id_bytes));
+        self.write(
+            &Collection::CATALOG,
+            catalog_id_key(collection.id),
+            Some(Bytes::from(to.as_bytes())),
+        );
 
-        old_value
+        Ok(())
     }
 
-    pub fn range<R>(&'_ mut self, _range: R) -> Range {
-        // For now, return an empty Range since we haven't implemented it yet
-        Range {}
+    pub(crate) fn collection_names(&mut self) -> io::Result<Vec<String>> {
+        self.scan_prefix_in(&Collection::CATALOG, &catalog_name_prefix())
+            .map(|entry| {
+                let (key, _id) = entry?;
+                Ok(String::from_utf8(key[1..].to_vec()).expect("corrupt catalog"))
+            })
+            .collect()
     }
 
-    pub fn commit(self) -> io::Result<()> {
-        self.db.commit(self.write_batch)
+    /// Fails if this transaction writes to a collection that no longer
+    /// exists. Reading the catalog entry also makes a concurrent drop
+    /// conflict with this transaction.
+    fn check_collections_exist(&mut self) -> io::Result<()> {
+        let written: Vec<CollectionId> = self.write_batches.keys().copied().collect();
+
+        for id in written {
+            if id == CollectionId::CATALOG || id == CollectionId::DEFAULT {
+                continue;
+            }
+
+            let id_key = catalog_id_key(id);
+
+            let dropped_by_this_tx = self
+                .write_batches
+                .get(&CollectionId::CATALOG)
+                .is_some_and(|catalog| catalog.get(&id_key) == Some(&None));
+
+            if !dropped_by_this_tx && self.read(&Collection::CATALOG, &id_key)?.is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "transaction wrote to a collection that has been dropped",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Atomically and durably apply this transaction's writes.
+    ///
+    /// Returns [`Conflict`] without applying anything if data read by this
+    /// transaction was modified by a concurrent commit, in which case the
+    /// transaction may be retried. Returns [`Rejected`] without applying
+    /// anything if a transactor refused the writes.
+    pub fn commit(mut self) -> Result<(), OneOf<(Conflict, Rejected, io::Error)>> {
+        self.check_collections_exist().map_err(OneOf::new)?;
+
+        let db = self.snapshot.db;
+        db.commit(self)
     }
 }
@@ -0,0 +1,254 @@
+use std::io;
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::SnapshotLsn;
+use crate::fs::Fs;
+use crate::util::{
+    Compression, FILE_HEADER_LEN, FileCipher, FrameScanner, Keys, RecordType, check_file_header,
+    deserialize_write_batch, serialize_write_batch, write_file_header,
+};
+use crate::{Lsn, WriteBatch};
+
+const WAL_FILE_NAME: &str = "log";
+const SEALED_PREFIX: &str = "log.";
+
+/// The write-ahead log. Every committed [`WriteBatch`] is appended as a
+/// single frame and fsynced before the commit is acknowledged.
+///
+/// Appends go to the active segment. A flush seals it by renaming it
+/// after the last `Lsn` it contains, and sealed segments are deleted
+/// once everything in them has been flushed to tables.
+pub(crate) struct Wal {
+    fs: Arc<dyn Fs + Send + Sync>,
+    directory: PathBuf,
+    compression: Compression,
+    keys: Keys,
+    /// Encrypts the active segment, as named by its header.
+    cipher: FileCipher,
+    len: u64,
+    /// The last `Lsn` of each sealed segment, in order.
+    sealed: Vec<Lsn>,
+}
+
+fn sealed_path(directory: &Path, last_lsn: Lsn) -> PathBuf {
+    directory.join(format!("{SEALED_PREFIX}{:016x}", last_lsn.value))
+}
+
+/// Where the valid part of a log segment ends.
+struct SegmentScan {
+    /// The length of the segment through its last valid frame.
+    valid_len: usize,
+    /// The `Lsn` of the last valid batch.
+    last_lsn: Option<Lsn>,
+    /// Whether a torn frame follows `valid_len`.
+    torn: bool,
+}
+
+/// Reads the batches of a log segment into `batches`, returning along
+/// with the scan the cipher that the segment is encrypted with.
+/// Corruption before the torn tail, if any, is an error.
+fn scan_segment(
+    buf: &[u8],
+    keys: &Keys,
+    batches: &mut Vec<(Lsn, WriteBatch)>,
+) -> io::Result<(SegmentScan, FileCipher)> {
+    let cipher = keys.for_file(check_file_header(buf, "log segment")?)?;
+
+    let mut scanner = FrameScanner::new(buf, FILE_HEADER_LEN, cipher.clone());
+    let mut last_lsn = None;
+
+    for frame in scanner.by_ref() {
+        let (record_type, body) = frame?;
+        record_type.expect(RecordType::WriteBatch)?;
+
+        let (lsn, batch) = deserialize_write_batch(&body)?;
+        last_lsn = Some(lsn);
+        batches.push((lsn, batch));
+    }
+
+    let scan = SegmentScan {
+        valid_len: scanner.valid_len(),
+        last_lsn,
+        torn: scanner.is_torn(),
+    };
+
+    Ok((scan, cipher))
+}
+
+/// The contents of a new segment encrypted with `cipher`, which holds no
+/// batches.
+fn empty_segment(cipher: &FileCipher) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    write_file_header(cipher, &mut buf)?;
+    Ok(buf)
+}
+
+fn read_file(fs: &dyn Fs, path: &Path) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; fs.len(path)? as usize];
+    fs.read_at_exact(path, 0, &mut buf)?;
+    Ok(buf)
+}
+
+impl Wal {
+    /// Opens (or creates) the log in `directory`, returning every batch
+    /// in its segments that was durably committed before the previous
+    /// shutdown or crash, along with the `Lsn` it was committed at.
+    /// Batches that were already flushed to tables are returned as well,
+    /// until their segments are released.
+    ///
+    /// A partially-written final frame is the expected result of crashing
+    /// during an append, so it is truncated away rather than treated as an
+    /// error. A bad frame before the end of the log is corruption.
+    pub(crate) fn recover(
+        fs: Arc<dyn Fs + Send + Sync>,
+        directory: &Path,
+        compression: Compression,
+        keys: Keys,
+    ) -> io::Result<(Wal, Vec<(Lsn, WriteBatch)>)> {
+        let path = directory.join(WAL_FILE_NAME);
+
+        if !fs.exists(&path)? {
+            fs.create_unique(&path, &empty_segment(&keys.for_writing())?)?;
+        }
+
+        let mut sealed = vec![];
+        for sealed_path in fs.list(directory, SEALED_PREFIX)? {
+            let last_lsn = sealed_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(SEALED_PREFIX))
+                .and_then(|suffix| u64::from_str_radix(suffix, 16).ok())
+                .and_then(NonZeroU64::new)
+                .map(|value| Lsn { value });
+
+            if let Some(last_lsn) = last_lsn {
+                sealed.push(last_lsn);
+            }
+        }
+        sealed.sort();
+
+        let mut batches = vec![];
+
+        for &last_lsn in &sealed {
+            let buf = read_file(&*fs, &sealed_path(directory, last_lsn))?;
+            let (scan, _) = scan_segment(&buf, &keys, &mut batches)?;
+
+            // sealed segments were synced in full before they were renamed
+            if scan.torn || scan.last_lsn != Some(last_lsn) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "sealed log segment {last_lsn:?} is truncated after {:?} at offset {}",
+                        scan.last_lsn, scan.valid_len,
+                    ),
+                ));
+            }
+        }
+
+        let buf = read_file(&*fs, &path)?;
+
+        // appends continue with the key of the active segment, which is
+        // replaced when the segment is next rotated
+        let (scan, cipher) = scan_segment(&buf, &keys, &mut batches)?;
+        if scan.torn {
+            fs.truncate(&path, scan.valid_len as u64)?;
+            fs.sync(&path)?;
+        }
+
+        let wal = Wal {
+            fs,
+            directory: directory.to_path_buf(),
+            compression,
+            keys,
+            cipher,
+            len: scan.valid_len as u64,
+            sealed,
+        };
+
+        Ok((wal, batches))
+    }
+
+    /// Seals the active segment, whose last batch was committed at
+    /// `last_lsn`, and starts a new one. Does nothing if the active
+    /// segment is empty.
+    pub(crate) fn rotate(&mut self, last_lsn: Lsn) -> io::Result<()> {
+        if self.len == FILE_HEADER_LEN as u64 {
+            return Ok(());
+        }
+
+        let path = self.directory.join(WAL_FILE_NAME);
+
+        self.fs
+            .rename(&path, &sealed_path(&self.directory, last_lsn))?;
+        self.sealed.push(last_lsn);
+
+        // also makes the rename durable, as both are in the directory
+        let cipher = self.keys.for_writing();
+        let buf = empty_segment(&cipher)?;
+        self.fs.create_unique(&path, &buf)?;
+
+        self.cipher = cipher;
+        self.len = buf.len() as u64;
+
+        Ok(())
+    }
+
+    /// The segments holding the batches after `flushed`: the sealed
+    /// segments, which are immutable, and the active segment along with
+    /// its length, after which later batches are appended.
+    pub(crate) fn segments_after(&self, flushed: SnapshotLsn) -> (Vec<PathBuf>, PathBuf, u64) {
+        let sealed = self
+            .sealed
+            .iter()
+            .filter(|&&last_lsn| Some(last_lsn) > flushed)
+            .map(|&last_lsn| sealed_path(&self.directory, last_lsn))
+            .collect();
+
+        (sealed, self.directory.join(WAL_FILE_NAME), self.len)
+    }
+
+    /// Deletes the sealed segments whose batches were all committed at or
+    /// before `flushed`.
+    pub(crate) fn release_through(&mut self, flushed: Lsn) -> io::Result<()> {
+        while let Some(&last_lsn) = self.sealed.first() {
+            if last_lsn > flushed {
+                break;
+            }
+
+            self.fs.delete(&sealed_path(&self.directory, last_lsn))?;
+            self.sealed.remove(0);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn append(&mut self, lsn: Lsn, batch: &WriteBatch) -> io::Result<()> {
+        let mut buf = vec![];
+        serialize_write_batch(
+            lsn,
+            batch,
+            self.compression,
+            &self.cipher,
+            self.len,
+            &mut buf,
+        )?;
+
+        let path = self.directory.join(WAL_FILE_NAME);
+
+        if let Err(e) = self
+            .fs
+            .append(&path, &buf)
+            .and_then(|()| self.fs.sync(&path))
+        {
+            // don't leave a torn frame in front of later appends
+            let _ = self.fs.truncate(&path, self.len);
+            return Err(e.into());
+        }
+
+        self.len += buf.len() as u64;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+use super::{Collection, InterestFilter};
+use crate::{CollectionId, Lsn, WriteBatch};
+
+/// A committed write batch delivered to a [`Subscriber`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// The log sequence number of the commit that wrote the batch, which
+    /// is greater than that of every commit before it.
+    pub lsn: u64,
+    /// The collection that the batch was written to.
+    pub collection: Collection,
+    /// The writes that the commit made to the collection, after
+    /// transactors were applied.
+    pub batch: WriteBatch,
+}
+
+/// Receives the committed write batches that match an
+/// [`InterestFilter`], in commit order. Returned from
+/// [`Db::watch`](super::Db::watch).
+///
+/// Events can be received by blocking, through [`Iterator`], or
+/// asynchronously through [`Subscriber::next_event`] or
+/// [`Subscriber::poll_next`]. Both end once the database is dropped and
+/// every event before that has been received. Events are buffered until
+/// they are received, so a subscriber that is no longer needed should be
+/// dropped.
+pub struct Subscriber {
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    cv: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<WatchEvent>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+impl Subscriber {
+    /// Waits for the next event, returning `None` once the database has
+    /// been dropped.
+    pub async fn next_event(&mut self) -> Option<WatchEvent> {
+        std::future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+
+    /// Polls for the next event, registering the waker of `cx` to be
+    /// woken when one is available.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<WatchEvent>> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if let Some(event) = state.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+
+        if state.closed {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+impl Iterator for Subscriber {
+    type Item = WatchEvent;
+
+    fn next(&mut self) -> Option<WatchEvent> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+
+            if state.closed {
+                return None;
+            }
+
+            state = self.shared.cv.wait(state).unwrap();
+        }
+    }
+}
+
+impl Shared {
+    fn push(&self, event: WatchEvent) {
+        let mut state = self.state.lock().unwrap();
+        state.events.push_back(event);
+        let waker = state.waker.take();
+        drop(state);
+
+        self.cv.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        let waker = state.waker.take();
+        drop(state);
+
+        self.cv.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+struct Subscription {
+    collection_id: CollectionId,
+    filter: InterestFilter,
+    shared: Weak<Shared>,
+}
+
+/// The subscribers of a database. Subscriptions are removed once their
+/// [`Subscriber`] is dropped, and every subscriber is closed when the
+/// database is dropped.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+
+impl Subscribers {
+    pub(crate) fn subscribe(&self, collection: &Collection, filter: InterestFilter) -> Subscriber {
+        let shared = Arc::new(Shared::default());
+
+        self.subscriptions.lock().unwrap().push(Subscription {
+            collection_id: collection.id,
+            filter,
+            shared: Arc::downgrade(&shared),
+        });
+
+        Subscriber { shared }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.subscriptions.lock().unwrap().is_empty()
+    }
+
+    /// Delivers the batch written to a collection by the commit at `lsn`
+    /// to every matching subscriber. Must be called in commit order.
+    pub(crate) fn publish(&self, lsn: Lsn, collection_id: CollectionId, batch: &WriteBatch) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+
+        subscriptions.retain(|subscription| {
+            let Some(shared) = subscription.shared.upgrade() else {
+                return false;
+            };
+
+            if subscription.collection_id == collection_id && subscription.filter.matches(batch) {
+                shared.push(WatchEvent {
+                    lsn: lsn.value.get(),
+                    collection: Collection { id: collection_id },
+                    batch: batch.clone(),
+                });
+            }
+
+            true
+        });
+    }
+}
+
+impl Drop for Subscribers {
+    fn drop(&mut self) {
+        for subscription in self.subscriptions.get_mut().unwrap().drain(..) {
+            if let Some(shared) = subscription.shared.upgrade() {
+                shared.close();
+            }
+        }
+    }
+}
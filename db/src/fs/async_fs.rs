@@ -1,60 +1,196 @@
-use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use drama::Executor;
+use drama::{Classification, Executor, TenantId};
 
-use super::{Error, FileAlreadyExists, FileDoesNotExist, Fs, Unavailable, UnexpectedEof};
+use super::{
+    Error, FileAlreadyExists, FileDoesNotExist, Fs, InvalidPath, LinkError, Unavailable,
+    UnexpectedEof,
+};
 
+/// Runs the blocking operations of an [`Fs`] on a pool of threads.
+///
+/// Arguments are owned so that they can move onto the pool, and
+/// [`AsyncFs::read_at_exact`] returns the buffer that it filled.
 pub(crate) struct AsyncFs {
     fs: Arc<dyn Fs + Send + Sync>,
     thread_pool: Executor,
+    tenant_id: TenantId,
 }
 
 impl AsyncFs {
-    pub fn new(fs: Arc<dyn Fs + Send + Sync>, threads: usize) -> AsyncFs {
+    pub fn new(fs: Arc<dyn Fs + Send + Sync>, threads: usize, tenant_id: TenantId) -> AsyncFs {
         AsyncFs {
             fs,
             thread_pool: Executor::new(threads),
+            tenant_id,
         }
     }
 
-    async fn read_at_exact(
+    pub async fn read_at_exact(
         &self,
-        path: &Path,
+        path: PathBuf,
         at: usize,
-        buf: &mut [u8],
-    ) -> Result<(), Error<(FileDoesNotExist, UnexpectedEof, Unavailable)>> {
-        let path: &'static Path = unsafe { std::mem::transmute(path) };
-        let buf: &'static mut [u8] = unsafe { std::mem::transmute(buf) };
+        len: usize,
+    ) -> Result<Vec<u8>, Error<(FileDoesNotExist, UnexpectedEof, InvalidPath, Unavailable)>> {
         let fs = self.fs.clone();
 
         self.thread_pool
-            .spawn(move || fs.read_at_exact(path, at, buf))
+            .spawn(self.tenant_id, Classification::Read, move || {
+                let mut buf = vec![0; len];
+                fs.read_at_exact(&path, at, &mut buf).map(|()| buf)
+            })
             .await
             .expect("fs thread crashed")
     }
 
-    async fn create_unique(
+    pub async fn create_unique(
         &self,
-        path: &Path,
-        buf: &[u8],
-    ) -> Result<(), Error<(FileAlreadyExists, Unavailable)>> {
-        let path: &'static Path = unsafe { std::mem::transmute(path) };
-        let buf: &'static [u8] = unsafe { std::mem::transmute(buf) };
+        path: PathBuf,
+        buf: Vec<u8>,
+    ) -> Result<(), Error<(FileAlreadyExists, InvalidPath, Unavailable)>> {
         let fs = self.fs.clone();
 
         self.thread_pool
-            .spawn(move || fs.create_unique(path, buf))
+            .spawn(self.tenant_id, Classification::Write, move || {
+                fs.create_unique(&path, &buf)
+            })
             .await
             .expect("fs thread crashed")
     }
 
-    async fn delete(&self, path: &Path) -> Result<(), Error<(FileDoesNotExist, Unavailable)>> {
-        let path: &'static Path = unsafe { std::mem::transmute(path) };
+    pub async fn delete(
+        &self,
+        path: PathBuf,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                fs.delete(&path)
+            })
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn append(
+        &self,
+        path: PathBuf,
+        buf: Vec<u8>,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                fs.append(&path, &buf)
+            })
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn truncate(
+        &self,
+        path: PathBuf,
+        len: u64,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                fs.truncate(&path, len)
+            })
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn sync(
+        &self,
+        path: PathBuf,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                fs.sync(&path)
+            })
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn sync_dir(
+        &self,
+        path: PathBuf,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                fs.sync_dir(&path)
+            })
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn hard_link(&self, from: PathBuf, to: PathBuf) -> Result<(), LinkError> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                fs.hard_link(&from, &to)
+            })
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn rename(
+        &self,
+        from: PathBuf,
+        to: PathBuf,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                fs.rename(&from, &to)
+            })
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn list(
+        &self,
+        directory: PathBuf,
+        prefix: String,
+    ) -> Result<Vec<PathBuf>, Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Read, move || {
+                fs.list(&directory, &prefix)
+            })
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn len(
+        &self,
+        path: PathBuf,
+    ) -> Result<u64, Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let fs = self.fs.clone();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Read, move || fs.len(&path))
+            .await
+            .expect("fs thread crashed")
+    }
+
+    pub async fn exists(&self, path: PathBuf) -> Result<bool, Error<(InvalidPath, Unavailable)>> {
         let fs = self.fs.clone();
 
         self.thread_pool
-            .spawn(move || fs.delete(path))
+            .spawn(self.tenant_id, Classification::Read, move || {
+                fs.exists(&path)
+            })
             .await
             .expect("fs thread crashed")
     }
@@ -1,12 +1,128 @@
-use std::path::Path;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use fs2::FileExt;
+use terrors::OneOf;
 
 use super::{
-    Error, FileAlreadyExists, FileDoesNotExist, Fs, Unavailable,
-    UnexpectedEof,
+    Error, FileAlreadyExists, FileDoesNotExist, Fs, InvalidPath, LinkError, Unavailable,
+    UnexpectedEof, Unsupported, read_exact_at, write_all_at,
 };
 
+/// An error for a path outside of the root.
+macro_rules! invalid_path {
+    () => {
+        Error {
+            at: concat!(file!(), ':', line!()),
+            kind: OneOf::new(InvalidPath),
+        }
+    };
+}
+
+/// Maps an error that means the file or directory is missing onto
+/// `FileDoesNotExist`, and any other onto `Unavailable`.
+macro_rules! missing_or_unavailable {
+    ($e:expr) => {
+        if $e.kind() == io::ErrorKind::NotFound {
+            Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(FileDoesNotExist),
+            }
+        } else {
+            Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(Unavailable),
+            }
+        }
+    };
+}
+
+/// Temporary files are hidden and carry this suffix, so that those left
+/// behind by a crash can be recognized and removed.
+const TEMPORARY_SUFFIX: &str = ".tmp";
+
+const LOCK_FILE_NAME: &str = "lock";
+
+/// Stores files in a directory of the local file system.
+///
+/// Paths are interpreted like those passed to [`std::fs`], and are
+/// refused with [`InvalidPath`] if they contain a `..` component or,
+/// except for the file that a hard link is made to, are outside of
+/// `root`. Symbolic links are not resolved.
+pub struct LocalFs {
+    root: PathBuf,
+    lock: Option<fs::File>,
+}
+
+impl LocalFs {
+    pub fn new<P: AsRef<Path>>(root: P) -> io::Result<LocalFs> {
+        Ok(LocalFs {
+            root: std::path::absolute(root)?,
+            lock: None,
+        })
+    }
+
+    /// Takes an exclusive lock on the root directory, held until this is
+    /// dropped, so that only one process opens the database at a time.
+    pub(crate) fn lock(&mut self) -> io::Result<()> {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.root.join(LOCK_FILE_NAME))?;
+
+        file.try_lock_exclusive()?;
+        self.lock = Some(file);
+
+        Ok(())
+    }
+
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        let path = std::path::absolute(path).ok()?;
+
+        let within_root = path
+            .strip_prefix(&self.root)
+            .ok()?
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+        within_root.then_some(path)
+    }
+
+    /// Removes the temporary files of writes that were interrupted by a
+    /// crash.
+    pub(crate) fn remove_temporary_files(&self) -> io::Result<()> {
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+
+            if file_name.starts_with('.') && file_name.ends_with(TEMPORARY_SUFFIX) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Makes changes to the entries of a directory durable.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    fs::File::open(path)?.sync_all()
+}
+
+fn write_temporary(path: &Path, buf: &[u8]) -> io::Result<()> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
 
-pub struct LocalFs {}
+    write_all_at(&file, buf, 0)?;
+    file.sync_all()
+}
 
 impl Fs for LocalFs {
     fn read_at_exact(
@@ -14,19 +130,300 @@ impl Fs for LocalFs {
         path: &Path,
         at: usize,
         buf: &mut [u8],
-    ) -> Result<(), Error<(FileDoesNotExist, UnexpectedEof, Unavailable)>> {
-        todo!()
+    ) -> Result<(), Error<(FileDoesNotExist, UnexpectedEof, InvalidPath, Unavailable)>> {
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error {
+                    at: concat!(file!(), ':', line!()),
+                    kind: OneOf::new(FileDoesNotExist),
+                });
+            }
+            Err(_) => {
+                return Err(Error {
+                    at: concat!(file!(), ':', line!()),
+                    kind: OneOf::new(Unavailable),
+                });
+            }
+        };
+
+        match read_exact_at(&file, buf, at as u64) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(UnexpectedEof),
+            }),
+            Err(_) => Err(Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(Unavailable),
+            }),
+        }
     }
 
     fn create_unique(
         &self,
         path: &Path,
         buf: &[u8],
-    ) -> Result<(), Error<(FileAlreadyExists, Unavailable)>> {
-        todo!()
+    ) -> Result<(), Error<(FileAlreadyExists, InvalidPath, Unavailable)>> {
+        let unavailable = |_: io::Error| Error {
+            at: concat!(file!(), ':', line!()),
+            kind: OneOf::new(Unavailable),
+        };
+
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+        let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(invalid_path!());
+        };
+
+        // The file is written in full under a temporary name, so that it
+        // never exists at `path` with partial contents
+        let temporary = parent.join(format!(
+            ".{}.{:016x}{TEMPORARY_SUFFIX}",
+            file_name.to_string_lossy(),
+            rand::random::<u64>(),
+        ));
+
+        if let Err(e) = write_temporary(&temporary, buf) {
+            let _ = fs::remove_file(&temporary);
+            return Err(unavailable(e));
+        }
+
+        // Unlike a rename, a link fails rather than replacing an existing
+        // file
+        let linked = fs::hard_link(&temporary, &path);
+        let _ = fs::remove_file(&temporary);
+
+        match linked {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(Error {
+                    at: concat!(file!(), ':', line!()),
+                    kind: OneOf::new(FileAlreadyExists),
+                });
+            }
+            Err(e) => return Err(unavailable(e)),
+        }
+
+        // Make the new directory entry durable as well
+        sync_dir(parent).map_err(unavailable)
+    }
+
+    fn delete(
+        &self,
+        path: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(FileDoesNotExist),
+            }),
+            Err(_) => Err(Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(Unavailable),
+            }),
+        }
     }
 
-    fn delete(&self, path: &Path) -> Result<(), Error<(FileDoesNotExist, Unavailable)>> {
-        todo!()
+    fn append(
+        &self,
+        path: &Path,
+        buf: &[u8],
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|e| missing_or_unavailable!(e))?;
+
+        file.write_all(buf).map_err(|_| Error {
+            at: concat!(file!(), ':', line!()),
+            kind: OneOf::new(Unavailable),
+        })
     }
+
+    fn truncate(
+        &self,
+        path: &Path,
+        len: u64,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+
+        fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_len(len))
+            .map_err(|e| missing_or_unavailable!(e))
+    }
+
+    fn sync(&self, path: &Path) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+
+        fs::File::open(path)
+            .and_then(|file| file.sync_all())
+            .map_err(|e| missing_or_unavailable!(e))
+    }
+
+    fn sync_dir(
+        &self,
+        path: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+
+        sync_dir(&path).map_err(|e| missing_or_unavailable!(e))
+    }
+
+    fn hard_link(&self, from: &Path, to: &Path) -> Result<(), LinkError> {
+        // the linked file is left unchanged, so it may be outside of root
+        let from = std::path::absolute(from)
+            .ok()
+            .filter(|from| {
+                from.components()
+                    .all(|component| component != Component::ParentDir)
+            })
+            .ok_or_else(|| invalid_path!())?;
+        let to = self.resolve(to).ok_or_else(|| invalid_path!())?;
+
+        let Err(e) = fs::hard_link(from, to) else {
+            return Ok(());
+        };
+
+        let at = concat!(file!(), ':', line!());
+
+        Err(match e.kind() {
+            io::ErrorKind::NotFound => Error {
+                at,
+                kind: OneOf::new(FileDoesNotExist),
+            },
+            io::ErrorKind::AlreadyExists => Error {
+                at,
+                kind: OneOf::new(FileAlreadyExists),
+            },
+            // some file systems refuse links with EPERM
+            io::ErrorKind::CrossesDevices
+            | io::ErrorKind::Unsupported
+            | io::ErrorKind::PermissionDenied => Error {
+                at,
+                kind: OneOf::new(Unsupported),
+            },
+            _ => Error {
+                at,
+                kind: OneOf::new(Unavailable),
+            },
+        })
+    }
+
+    fn rename(
+        &self,
+        from: &Path,
+        to: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let from = self.resolve(from).ok_or_else(|| invalid_path!())?;
+        let to = self.resolve(to).ok_or_else(|| invalid_path!())?;
+
+        fs::rename(from, to).map_err(|e| missing_or_unavailable!(e))
+    }
+
+    fn list(
+        &self,
+        directory: &Path,
+        prefix: &str,
+    ) -> Result<Vec<PathBuf>, Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let directory = self.resolve(directory).ok_or_else(|| invalid_path!())?;
+
+        let mut paths = vec![];
+
+        for entry in fs::read_dir(&directory).map_err(|e| missing_or_unavailable!(e))? {
+            let entry = entry.map_err(|e| missing_or_unavailable!(e))?;
+
+            let is_file = entry
+                .file_type()
+                .map_err(|e| missing_or_unavailable!(e))?
+                .is_file();
+
+            let file_name = entry.file_name();
+            if is_file
+                && file_name
+                    .to_str()
+                    .is_some_and(|name| name.starts_with(prefix))
+            {
+                paths.push(entry.path());
+            }
+        }
+
+        paths.sort();
+
+        Ok(paths)
+    }
+
+    fn len(&self, path: &Path) -> Result<u64, Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+
+        fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .map_err(|e| missing_or_unavailable!(e))
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, Error<(InvalidPath, Unavailable)>> {
+        let path = self.resolve(path).ok_or_else(|| invalid_path!())?;
+
+        path.try_exists().map_err(|_| Error {
+            at: concat!(file!(), ':', line!()),
+            kind: OneOf::new(Unavailable),
+        })
+    }
+}
+
+#[test]
+fn smoke_local_fs() {
+    let root = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+
+    let local_fs = LocalFs::new(&root).unwrap();
+    let path = root.join("file");
+
+    local_fs.create_unique(&path, b"hello").unwrap();
+    let exists = local_fs.create_unique(&path, b"other").unwrap_err();
+    assert!(exists.kind.narrow::<FileAlreadyExists, _>().is_ok());
+
+    let mut buf = [0; 4];
+    local_fs.read_at_exact(&path, 1, &mut buf).unwrap();
+    assert_eq!(&buf, b"ello");
+
+    let eof = local_fs.read_at_exact(&path, 2, &mut buf).unwrap_err();
+    assert!(eof.kind.narrow::<UnexpectedEof, _>().is_ok());
+
+    let missing = local_fs
+        .read_at_exact(&root.join("missing"), 0, &mut buf)
+        .unwrap_err();
+    assert!(missing.kind.narrow::<FileDoesNotExist, _>().is_ok());
+
+    for outside in [root.join("..").join("file"), root.with_extension("sibling")] {
+        let refused = local_fs.create_unique(&outside, b"escaped").unwrap_err();
+        assert!(refused.kind.narrow::<InvalidPath, _>().is_ok());
+        assert!(!outside.exists());
+    }
+
+    // only the created file is left, without temporary files
+    let names: Vec<_> = std::fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names, ["file"]);
+
+    std::fs::write(root.join(".file.0123456789abcdef.tmp"), b"torn").unwrap();
+    local_fs.remove_temporary_files().unwrap();
+    assert_eq!(std::fs::read_dir(&root).unwrap().count(), 1);
+
+    local_fs.delete(&path).unwrap();
+    let deleted = local_fs.delete(&path).unwrap_err();
+    assert!(deleted.kind.narrow::<FileDoesNotExist, _>().is_ok());
+
+    std::fs::remove_dir_all(&root).unwrap();
 }
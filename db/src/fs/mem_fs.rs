@@ -2,13 +2,148 @@ use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
+use fault_injection::maybe;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use terrors::OneOf;
 
-use super::{Error, FileAlreadyExists, FileDoesNotExist, Fs, Unavailable, UnexpectedEof};
+use super::{
+    Error, FileAlreadyExists, FileDoesNotExist, Fs, InvalidPath, LinkError, Unavailable,
+    UnexpectedEof, Unsupported,
+};
 
+/// Returns `Unavailable` from the enclosing operation when
+/// [`fault_injection::FAULT_INJECT_COUNTER`] runs out, like the I/O of
+/// [`LocalFs`](super::LocalFs) does.
+macro_rules! injected_fault {
+    () => {
+        if maybe!(Ok::<(), std::io::Error>(())).is_err() {
+            return Err(Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(Unavailable),
+            });
+        }
+    };
+}
+
+macro_rules! file_does_not_exist {
+    () => {
+        Error {
+            at: concat!(file!(), ':', line!()),
+            kind: OneOf::new(FileDoesNotExist),
+        }
+    };
+}
+
+/// Keeps files in memory, tracking which writes would survive a crash
+/// so that [`MemFs::crash`] can simulate one.
+///
+/// Like a file system on a disk, the contents of a file are durable as
+/// of its last [`Fs::sync`], and its name as of the last [`Fs::sync_dir`]
+/// of its directory. Clones share the same files.
 #[derive(Clone)]
 pub struct MemFs {
-    files: Arc<Mutex<BTreeMap<PathBuf, Box<[u8]>>>>,
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    /// The file that each path currently refers to.
+    names: BTreeMap<PathBuf, u64>,
+    /// The file that each path would refer to after a crash.
+    durable_names: BTreeMap<PathBuf, u64>,
+    files: BTreeMap<u64, File>,
+    next_file_id: u64,
+    rng: StdRng,
+    hard_links: bool,
+}
+
+struct File {
+    data: Vec<u8>,
+    /// The contents as of the last sync.
+    durable: Vec<u8>,
+}
+
+impl Default for MemFs {
+    fn default() -> MemFs {
+        MemFs::with_seed(0)
+    }
+}
+
+impl MemFs {
+    /// Creates an empty file system whose crashes tear unsynced writes
+    /// as chosen by an RNG seeded with `seed`.
+    pub fn with_seed(seed: u64) -> MemFs {
+        MemFs {
+            state: Arc::new(Mutex::new(State {
+                names: BTreeMap::new(),
+                durable_names: BTreeMap::new(),
+                files: BTreeMap::new(),
+                next_file_id: 0,
+                rng: StdRng::seed_from_u64(seed),
+                hard_links: true,
+            })),
+        }
+    }
+
+    /// Makes [`Fs::hard_link`] fail with [`Unsupported`], like a file
+    /// system that can't link files.
+    pub fn disable_hard_links(&self) {
+        self.state.lock().unwrap().hard_links = false;
+    }
+
+    /// Loses everything that was not made durable, as if the machine had
+    /// lost power.
+    ///
+    /// Files are renamed, removed and created back to the last sync of
+    /// their directory. A file that was only appended to since it was
+    /// last synced keeps a random prefix of the appended bytes, as a disk
+    /// may persist some of the pages of an interrupted write, and any
+    /// other file reverts to its synced contents.
+    pub fn crash(&self) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        state.names = state.durable_names.clone();
+
+        let names = &state.names;
+        state
+            .files
+            .retain(|file_id, _| names.values().any(|id| id == file_id));
+
+        for file in state.files.values_mut() {
+            if file.data.starts_with(&file.durable) {
+                let appended = file.data.len() - file.durable.len();
+                let torn_len = file.durable.len() + state.rng.random_range(0..=appended);
+                file.data.truncate(torn_len);
+            } else {
+                file.data = file.durable.clone();
+            }
+
+            file.durable = file.data.clone();
+        }
+    }
+}
+
+impl State {
+    fn file(&self, path: &Path) -> Option<&File> {
+        self.files.get(self.names.get(path)?)
+    }
+
+    fn file_mut(&mut self, path: &Path) -> Option<&mut File> {
+        self.files.get_mut(self.names.get(path)?)
+    }
+
+    /// Drops files that no path refers to, now or after a crash.
+    fn forget_unnamed(&mut self) {
+        let (names, durable_names) = (&self.names, &self.durable_names);
+
+        self.files.retain(|file_id, _| {
+            names
+                .values()
+                .chain(durable_names.values())
+                .any(|id| id == file_id)
+        });
+    }
 }
 
 impl Fs for MemFs {
@@ -17,24 +152,23 @@ impl Fs for MemFs {
         path: &Path,
         at: usize,
         buf: &mut [u8],
-    ) -> Result<(), Error<(FileDoesNotExist, UnexpectedEof, Unavailable)>> {
-        let files = self.files.lock().unwrap();
+    ) -> Result<(), Error<(FileDoesNotExist, UnexpectedEof, InvalidPath, Unavailable)>> {
+        injected_fault!();
 
-        let Some(file) = files.get(path) else {
-            return Err(Error {
-                at: concat!(file!(), ':', line!()),
-                kind: OneOf::new(FileDoesNotExist),
-            });
+        let state = self.state.lock().unwrap();
+
+        let Some(file) = state.file(path) else {
+            return Err(file_does_not_exist!());
         };
 
-        if file.len() < buf.len() + at {
+        if file.data.len() < buf.len() + at {
             return Err(Error {
                 at: concat!(file!(), ':', line!()),
                 kind: OneOf::new(UnexpectedEof),
             });
         }
 
-        buf.copy_from_slice(&file[at..at + buf.len()]);
+        buf.copy_from_slice(&file.data[at..at + buf.len()]);
 
         Ok(())
     }
@@ -43,24 +177,290 @@ impl Fs for MemFs {
         &self,
         path: &Path,
         buf: &[u8],
-    ) -> Result<(), Error<(FileAlreadyExists, Unavailable)>> {
-        let mut files = self.files.lock().unwrap();
+    ) -> Result<(), Error<(FileAlreadyExists, InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.names.contains_key(path) {
+            return Err(Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(FileAlreadyExists),
+            });
+        }
+
+        let file_id = state.next_file_id;
+        state.next_file_id += 1;
+
+        state.files.insert(
+            file_id,
+            File {
+                data: buf.to_vec(),
+                durable: buf.to_vec(),
+            },
+        );
+        state.names.insert(path.to_path_buf(), file_id);
+
+        // the directory is synced as well
+        let durable_names = sync_dir(&state.names, &state.durable_names, path.parent());
+        state.durable_names = durable_names;
+        state.forget_unnamed();
+
+        Ok(())
+    }
+
+    fn delete(
+        &self,
+        path: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.names.remove(path).is_none() {
+            return Err(file_does_not_exist!());
+        }
+
+        state.forget_unnamed();
+
+        Ok(())
+    }
+
+    fn append(
+        &self,
+        path: &Path,
+        buf: &[u8],
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let Some(file) = state.names.get(path).and_then(|id| state.files.get_mut(id)) else {
+            return Err(file_does_not_exist!());
+        };
+
+        // an injected fault interrupts the write part of the way through
+        if maybe!(Ok::<(), std::io::Error>(())).is_err() {
+            let written = state.rng.random_range(0..=buf.len());
+            file.data.extend_from_slice(&buf[..written]);
+
+            return Err(Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(Unavailable),
+            });
+        }
 
-        if files.contains_key(path) {
+        file.data.extend_from_slice(buf);
+
+        Ok(())
+    }
+
+    fn truncate(
+        &self,
+        path: &Path,
+        len: u64,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        let mut state = self.state.lock().unwrap();
+
+        let Some(file) = state.file_mut(path) else {
+            return Err(file_does_not_exist!());
+        };
+
+        file.data.resize(len as usize, 0);
+
+        Ok(())
+    }
+
+    fn sync(&self, path: &Path) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        let mut state = self.state.lock().unwrap();
+
+        let Some(file) = state.file_mut(path) else {
+            return Err(file_does_not_exist!());
+        };
+
+        file.durable = file.data.clone();
+
+        Ok(())
+    }
+
+    fn sync_dir(
+        &self,
+        path: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        let mut state = self.state.lock().unwrap();
+
+        // directories exist implicitly
+        let durable_names = sync_dir(&state.names, &state.durable_names, Some(path));
+        state.durable_names = durable_names;
+        state.forget_unnamed();
+
+        Ok(())
+    }
+
+    fn hard_link(&self, from: &Path, to: &Path) -> Result<(), LinkError> {
+        injected_fault!();
+
+        let mut state = self.state.lock().unwrap();
+
+        if !state.hard_links {
+            return Err(Error {
+                at: concat!(file!(), ':', line!()),
+                kind: OneOf::new(Unsupported),
+            });
+        }
+
+        let Some(&file_id) = state.names.get(from) else {
+            return Err(file_does_not_exist!());
+        };
+
+        if state.names.contains_key(to) {
             return Err(Error {
                 at: concat!(file!(), ':', line!()),
                 kind: OneOf::new(FileAlreadyExists),
             });
         }
 
-        let pb = PathBuf::from(path);
+        state.names.insert(to.to_path_buf(), file_id);
+
+        Ok(())
+    }
+
+    fn rename(
+        &self,
+        from: &Path,
+        to: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        let mut state = self.state.lock().unwrap();
 
-        files.insert(pb, buf.into());
+        let Some(file_id) = state.names.remove(from) else {
+            return Err(file_does_not_exist!());
+        };
+
+        state.names.insert(to.to_path_buf(), file_id);
+        state.forget_unnamed();
 
         Ok(())
     }
 
-    fn delete(&self, path: &Path) -> Result<(), Error<(FileDoesNotExist, Unavailable)>> {
-        todo!()
+    fn list(
+        &self,
+        directory: &Path,
+        prefix: &str,
+    ) -> Result<Vec<PathBuf>, Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .names
+            .keys()
+            .filter(|path| {
+                path.parent() == Some(directory)
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with(prefix))
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn len(&self, path: &Path) -> Result<u64, Error<(FileDoesNotExist, InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        let state = self.state.lock().unwrap();
+
+        let Some(file) = state.file(path) else {
+            return Err(file_does_not_exist!());
+        };
+
+        Ok(file.data.len() as u64)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, Error<(InvalidPath, Unavailable)>> {
+        injected_fault!();
+
+        Ok(self.state.lock().unwrap().names.contains_key(path))
     }
 }
+
+/// The durable names after syncing `directory`, which are the current
+/// names within it and the previously durable names elsewhere.
+fn sync_dir(
+    names: &BTreeMap<PathBuf, u64>,
+    durable_names: &BTreeMap<PathBuf, u64>,
+    directory: Option<&Path>,
+) -> BTreeMap<PathBuf, u64> {
+    let in_directory = |path: &PathBuf| path.parent() == directory;
+
+    durable_names
+        .iter()
+        .filter(|(path, _)| !in_directory(path))
+        .chain(names.iter().filter(|(path, _)| in_directory(path)))
+        .map(|(path, file_id)| (path.clone(), *file_id))
+        .collect()
+}
+
+#[test]
+fn smoke_mem_fs_crash() {
+    let directory = Path::new("directory");
+    let synced = directory.join("synced");
+    let appended = directory.join("appended");
+    let renamed = directory.join("renamed");
+    let unlinked = directory.join("unlinked");
+
+    for seed in 0..16 {
+        let fs = MemFs::with_seed(seed);
+
+        fs.create_unique(&synced, b"a").unwrap();
+        fs.append(&synced, b"b").unwrap();
+        fs.sync(&synced).unwrap();
+
+        fs.create_unique(&appended, b"a").unwrap();
+        fs.append(&appended, b"bcd").unwrap();
+
+        fs.create_unique(&unlinked, b"a").unwrap();
+        fs.delete(&unlinked).unwrap();
+        fs.rename(&synced, &renamed).unwrap();
+
+        // changes to a file that are overwritten rather than appended are
+        // lost entirely
+        fs.truncate(&renamed, 1).unwrap();
+        fs.append(&renamed, b"c").unwrap();
+
+        fs.crash();
+
+        // the rename and removal were never made durable
+        assert!(!fs.exists(&renamed).unwrap());
+        assert!(fs.exists(&unlinked).unwrap());
+
+        let mut buf = [0; 2];
+        fs.read_at_exact(&synced, 0, &mut buf).unwrap();
+        assert_eq!(&buf, b"ab");
+
+        let len = fs.len(&appended).unwrap() as usize;
+        let mut buf = vec![0; len];
+        fs.read_at_exact(&appended, 0, &mut buf).unwrap();
+        assert!(b"abcd".starts_with(&buf) && len >= 1);
+
+        // what survived a crash is durable
+        fs.crash();
+        assert_eq!(fs.len(&appended).unwrap() as usize, len);
+    }
+
+    // a crash after syncing the directory keeps the rename
+    let fs = MemFs::default();
+    fs.create_unique(&synced, b"a").unwrap();
+    fs.rename(&synced, &renamed).unwrap();
+    fs.sync_dir(directory).unwrap();
+    fs.crash();
+    assert!(!fs.exists(&synced).unwrap());
+    assert!(fs.exists(&renamed).unwrap());
+}
@@ -1,28 +1,93 @@
 mod async_fs;
 mod local_fs;
+#[cfg(test)]
 mod mem_fs;
 
 pub(crate) use async_fs::AsyncFs;
+pub(crate) use local_fs::LocalFs;
+#[cfg(test)]
+pub(crate) use mem_fs::MemFs;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use terrors::{OneOf, TypeSet};
 
+/// The file operations used by the database, so that tests can run
+/// against an in-memory implementation.
+///
+/// Writes are only durable once the file has been passed to
+/// [`Fs::sync`], and the creation, renaming or removal of a file only
+/// once its directory has been passed to [`Fs::sync_dir`], except for
+/// [`Fs::create_unique`], which syncs both before returning.
 pub trait Fs {
     fn read_at_exact(
         &self,
         path: &Path,
         at: usize,
         buf: &mut [u8],
-    ) -> Result<(), Error<(FileDoesNotExist, UnexpectedEof, Unavailable)>>;
+    ) -> Result<(), Error<(FileDoesNotExist, UnexpectedEof, InvalidPath, Unavailable)>>;
 
     fn create_unique(
         &self,
         path: &Path,
         buf: &[u8],
-    ) -> Result<(), Error<(FileAlreadyExists, Unavailable)>>;
+    ) -> Result<(), Error<(FileAlreadyExists, InvalidPath, Unavailable)>>;
 
-    fn delete(&self, path: &Path) -> Result<(), Error<(FileDoesNotExist, Unavailable)>>;
+    fn delete(
+        &self,
+        path: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>>;
+
+    /// Writes `buf` at the end of an existing file.
+    fn append(
+        &self,
+        path: &Path,
+        buf: &[u8],
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>>;
+
+    /// Shortens a file to `len` bytes.
+    fn truncate(
+        &self,
+        path: &Path,
+        len: u64,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>>;
+
+    /// Makes the contents of a file durable.
+    fn sync(&self, path: &Path) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>>;
+
+    /// Makes the creation, renaming and removal of the files in a
+    /// directory durable.
+    fn sync_dir(
+        &self,
+        path: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>>;
+
+    /// Makes `to` another name of the file at `from`, without copying its
+    /// contents. Returns [`Unsupported`] if the file system can't link
+    /// the two paths. The new name is durable once its directory is
+    /// synced. As `from` is left unchanged, it may be outside of the
+    /// directory that the file system is confined to.
+    fn hard_link(&self, from: &Path, to: &Path) -> Result<(), LinkError>;
+
+    /// Atomically moves a file to `to`, replacing any file already there.
+    fn rename(
+        &self,
+        from: &Path,
+        to: &Path,
+    ) -> Result<(), Error<(FileDoesNotExist, InvalidPath, Unavailable)>>;
+
+    /// The paths of the files in `directory` whose names start with
+    /// `prefix`, in order.
+    fn list(
+        &self,
+        directory: &Path,
+        prefix: &str,
+    ) -> Result<Vec<PathBuf>, Error<(FileDoesNotExist, InvalidPath, Unavailable)>>;
+
+    /// The length of a file in bytes.
+    fn len(&self, path: &Path) -> Result<u64, Error<(FileDoesNotExist, InvalidPath, Unavailable)>>;
+
+    fn exists(&self, path: &Path) -> Result<bool, Error<(InvalidPath, Unavailable)>>;
 }
 
 #[derive(Debug, Clone)]
@@ -35,18 +100,91 @@ where
     kind: OneOf<T>,
 }
 
+/// The [`std::io::ErrorKind`] that an error is reported as once it is
+/// converted into a [`std::io::Error`].
+pub trait IoErrorKind {
+    const KIND: std::io::ErrorKind;
+}
+
+macro_rules! impl_from_error {
+    ($enum:ident, $($variant:ident),+) => {
+        impl<$($variant),+> From<Error<($($variant,)+)>> for std::io::Error
+        where
+            $($variant: IoErrorKind + 'static,)+
+            OneOf<($($variant,)+)>: std::fmt::Debug + Clone,
+        {
+            fn from(error: Error<($($variant,)+)>) -> std::io::Error {
+                let kind = match error.kind.as_enum() {
+                    $(terrors::$enum::$variant(_) => $variant::KIND,)+
+                };
+
+                std::io::Error::new(kind, format!("{:?} at {}", error.kind, error.at))
+            }
+        }
+    };
+}
+
+impl_from_error!(E1, A);
+impl_from_error!(E2, A, B);
+impl_from_error!(E3, A, B, C);
+impl_from_error!(E4, A, B, C, D);
+impl_from_error!(E5, A, B, C, D, E);
+
 #[derive(Clone, Copy, Debug)]
 pub struct FileAlreadyExists;
 
+impl IoErrorKind for FileAlreadyExists {
+    const KIND: std::io::ErrorKind = std::io::ErrorKind::AlreadyExists;
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct FileDoesNotExist;
 
+impl IoErrorKind for FileDoesNotExist {
+    const KIND: std::io::ErrorKind = std::io::ErrorKind::NotFound;
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct UnexpectedEof;
 
+impl IoErrorKind for UnexpectedEof {
+    const KIND: std::io::ErrorKind = std::io::ErrorKind::UnexpectedEof;
+}
+
+/// The path is outside of the directory that the file system is
+/// confined to.
+#[derive(Clone, Copy, Debug)]
+pub struct InvalidPath;
+
+impl IoErrorKind for InvalidPath {
+    const KIND: std::io::ErrorKind = std::io::ErrorKind::InvalidInput;
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Unavailable;
 
+impl IoErrorKind for Unavailable {
+    const KIND: std::io::ErrorKind = std::io::ErrorKind::Other;
+}
+
+/// The errors of [`Fs::hard_link`].
+pub type LinkError = Error<(
+    FileDoesNotExist,
+    FileAlreadyExists,
+    InvalidPath,
+    Unsupported,
+    Unavailable,
+)>;
+
+/// The file system can't perform the operation, such as linking files on
+/// different devices.
+#[derive(Clone, Copy, Debug)]
+pub struct Unsupported;
+
+impl IoErrorKind for Unsupported {
+    const KIND: std::io::ErrorKind = std::io::ErrorKind::Unsupported;
+}
+
 #[cfg(unix)]
 mod unix;
 
@@ -58,3 +196,154 @@ mod windows;
 
 #[cfg(windows)]
 use windows::{read_exact_at, write_all_at};
+
+/// Links `to` in `to_fs` to the file at `from`, or copies the file from
+/// `from_fs` if it can't be linked. Either way, the new name is durable
+/// once its directory is synced.
+pub(crate) fn link_or_copy(
+    from_fs: &dyn Fs,
+    from: &Path,
+    to_fs: &dyn Fs,
+    to: &Path,
+) -> std::io::Result<()> {
+    let Err(error) = to_fs.hard_link(from, to) else {
+        return Ok(());
+    };
+
+    if let Err(kind) = error.kind.narrow::<Unsupported, _>() {
+        return Err(Error { at: error.at, kind }.into());
+    }
+
+    let mut buf = vec![0; from_fs.len(from)? as usize];
+    from_fs.read_at_exact(from, 0, &mut buf)?;
+    to_fs.create_unique(to, &buf)?;
+
+    Ok(())
+}
+
+/// Exercises the operations used by logs and recovery, which behave the
+/// same for every implementation.
+#[cfg(test)]
+fn exercise_fs(fs: &dyn Fs, directory: &Path) {
+    let log = directory.join("log.1");
+    let rotated = directory.join("log.2");
+    let missing = directory.join("missing");
+
+    fs.create_unique(&log, b"ab").unwrap();
+    fs.append(&log, b"cd").unwrap();
+    fs.append(&log, b"ef").unwrap();
+    fs.truncate(&log, 4).unwrap();
+    fs.sync(&log).unwrap();
+    assert_eq!(fs.len(&log).unwrap(), 4);
+
+    let mut buf = [0; 4];
+    fs.read_at_exact(&log, 0, &mut buf).unwrap();
+    assert_eq!(&buf, b"abcd");
+
+    let error = fs.append(&missing, b"x").unwrap_err();
+    assert!(error.kind.narrow::<FileDoesNotExist, _>().is_ok());
+    let error = fs.len(&missing).unwrap_err();
+    assert!(error.kind.narrow::<FileDoesNotExist, _>().is_ok());
+
+    // errors keep their kind once converted
+    let error = std::io::Error::from(fs.len(&missing).unwrap_err());
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    let error = std::io::Error::from(fs.create_unique(&log, b"").unwrap_err());
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    let error = std::io::Error::from(fs.read_at_exact(&log, 2, &mut buf).unwrap_err());
+    assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+    fs.rename(&log, &rotated).unwrap();
+    fs.sync_dir(directory).unwrap();
+    assert!(!fs.exists(&log).unwrap());
+    assert!(fs.exists(&rotated).unwrap());
+    let error = fs.rename(&log, &rotated).unwrap_err();
+    assert!(error.kind.narrow::<FileDoesNotExist, _>().is_ok());
+
+    fs.create_unique(&directory.join("log.3"), b"").unwrap();
+    fs.create_unique(&directory.join("other"), b"").unwrap();
+    assert_eq!(
+        fs.list(directory, "log.").unwrap(),
+        [rotated.clone(), directory.join("log.3")]
+    );
+
+    // renaming replaces an existing file
+    fs.rename(&directory.join("log.3"), &rotated).unwrap();
+    assert_eq!(fs.len(&rotated).unwrap(), 0);
+    assert_eq!(
+        fs.list(directory, "log.").unwrap(),
+        std::slice::from_ref(&rotated)
+    );
+
+    // a link names the same file, and outlives the original name
+    let linked = directory.join("linked");
+    fs.append(&rotated, b"gh").unwrap();
+    fs.hard_link(&rotated, &linked).unwrap();
+    let error = fs.hard_link(&rotated, &linked).unwrap_err();
+    assert!(error.kind.narrow::<FileAlreadyExists, _>().is_ok());
+    let error = fs
+        .hard_link(&missing, &directory.join("other link"))
+        .unwrap_err();
+    assert!(error.kind.narrow::<FileDoesNotExist, _>().is_ok());
+    fs.delete(&rotated).unwrap();
+    let mut buf = [0; 2];
+    fs.read_at_exact(&linked, 0, &mut buf).unwrap();
+    assert_eq!(&buf, b"gh");
+
+    link_or_copy(fs, &linked, fs, &directory.join("copied")).unwrap();
+    assert_eq!(fs.len(&directory.join("copied")).unwrap(), 2);
+    let error = link_or_copy(fs, &linked, fs, &directory.join("copied")).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn smoke_fs() {
+    exercise_fs(&MemFs::default(), Path::new("directory"));
+
+    let root = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+
+    exercise_fs(&LocalFs::new(&root).unwrap(), &root);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn smoke_async_fs() {
+    use crate::util::block_on;
+
+    let fs = AsyncFs::new(
+        std::sync::Arc::new(MemFs::default()),
+        2,
+        drama::TenantId::new("smoke_async_fs"),
+    );
+    let directory = PathBuf::from("directory");
+    let log = directory.join("log.1");
+    let linked = directory.join("log.2");
+
+    block_on(async {
+        fs.create_unique(log.clone(), b"ab".to_vec()).await.unwrap();
+        fs.append(log.clone(), b"cdef".to_vec()).await.unwrap();
+        fs.truncate(log.clone(), 4).await.unwrap();
+        fs.sync(log.clone()).await.unwrap();
+        assert_eq!(fs.len(log.clone()).await.unwrap(), 4);
+        assert_eq!(fs.read_at_exact(log.clone(), 1, 3).await.unwrap(), b"bcd");
+
+        fs.hard_link(log.clone(), linked.clone()).await.unwrap();
+        fs.delete(log.clone()).await.unwrap();
+        fs.rename(linked.clone(), log.clone()).await.unwrap();
+        fs.sync_dir(directory.clone()).await.unwrap();
+        assert!(fs.exists(log.clone()).await.unwrap());
+        assert!(!fs.exists(linked.clone()).await.unwrap());
+        assert_eq!(
+            fs.list(directory.clone(), "log.".to_string())
+                .await
+                .unwrap(),
+            [log]
+        );
+
+        let error = fs.len(linked).await.unwrap_err();
+        assert!(error.kind.narrow::<FileDoesNotExist, _>().is_ok());
+    });
+}
@@ -0,0 +1,129 @@
+use std::io;
+use std::num::NonZeroU64;
+use std::path::Path;
+
+use crate::object_store::ObjectId;
+
+/// Stores the blobs of objects in a [`marble`] heap, in a directory of
+/// its own, keyed by [`ObjectId`].
+///
+/// Each batch is written atomically and durably into a new file. Space
+/// used by replaced and removed items is reclaimed by
+/// [`Heap::maintenance`], which rewrites the live items of fragmented
+/// files.
+#[derive(Clone)]
+pub(crate) struct Heap {
+    marble: marble::Marble,
+}
+
+/// Files whose items are less than this percent live are rewritten by
+/// [`Heap::maintenance`].
+const FILE_COMPACTION_PERCENT: u8 = 66;
+
+impl Heap {
+    pub(crate) fn open(directory: &Path) -> io::Result<Heap> {
+        let config = marble::Config {
+            path: directory.to_path_buf(),
+            file_compaction_percent: FILE_COMPACTION_PERCENT,
+            // items are referenced by commits once the batch is written
+            fsync_each_batch: true,
+            ..marble::Config::default()
+        };
+
+        Ok(Heap {
+            marble: config.open()?,
+        })
+    }
+
+    /// Writes a batch of items, removing those that map to `None`.
+    pub(crate) fn write_batch<B, I>(&self, batch: I) -> io::Result<()>
+    where
+        B: AsRef<[u8]>,
+        I: IntoIterator<Item = (ObjectId, Option<B>)>,
+    {
+        let batch: Vec<(u64, Option<B>)> = batch
+            .into_iter()
+            .map(|(id, bytes_opt)| (id.value.get(), bytes_opt))
+            .collect();
+
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        self.marble.write_batch(batch)
+    }
+
+    pub(crate) fn read(&self, id: ObjectId) -> io::Result<Option<Box<[u8]>>> {
+        self.marble.read(id.value.get())
+    }
+
+    /// Every id that holds an item or the tombstone of a removed item.
+    pub(crate) fn ids(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        self.marble
+            .allocated_object_ids()
+            .filter_map(NonZeroU64::new)
+            .map(|value| ObjectId { value })
+    }
+
+    /// Rewrites the live items of fragmented files, returning the number
+    /// of items that were rewritten.
+    pub(crate) fn maintenance(&self) -> io::Result<usize> {
+        self.marble.maintenance()
+    }
+
+    /// Whether less than [`FILE_COMPACTION_PERCENT`] of the stored items
+    /// are live, so that [`Heap::maintenance`] has space to reclaim. This
+    /// bounds the space taken by dead items to about half of that taken
+    /// by live ones.
+    pub(crate) fn needs_maintenance(&self) -> bool {
+        let stats = self.marble.stats();
+        stats.dead_objects > 0 && stats.live_ratio * 100.0 < f32::from(FILE_COMPACTION_PERCENT)
+    }
+
+    /// The number of heap files and the sum of their sizes.
+    pub(crate) fn file_stats(&self) -> (u64, u64) {
+        let stats = self.marble.stats();
+        (stats.files as u64, stats.total_file_size)
+    }
+}
+
+#[test]
+fn smoke_heap() {
+    let directory = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let object = |value| ObjectId {
+        value: NonZeroU64::new(value).unwrap(),
+    };
+
+    {
+        let heap = Heap::open(&directory).unwrap();
+        heap.write_batch([(object(1), Some(b"object")), (object(3), Some(b"second"))])
+            .unwrap();
+        heap.write_batch([(object(1), None::<&[u8]>)]).unwrap();
+        heap.write_batch([(object(2), Some(b"object"))]).unwrap();
+    }
+
+    let heap = Heap::open(&directory).unwrap();
+    assert_eq!(heap.read(object(1)).unwrap(), None);
+    assert_eq!(&*heap.read(object(2)).unwrap().unwrap(), b"object");
+    assert_eq!(&*heap.read(object(3)).unwrap().unwrap(), b"second");
+    assert_eq!(
+        heap.ids().collect::<Vec<_>>(),
+        [object(1), object(2), object(3)]
+    );
+    assert_eq!(heap.file_stats().0, 3);
+
+    assert!(!heap.needs_maintenance());
+
+    // the first file now holds nothing live
+    heap.write_batch([(object(3), None::<&[u8]>)]).unwrap();
+    assert!(heap.needs_maintenance());
+
+    heap.maintenance().unwrap();
+    assert!(!heap.needs_maintenance());
+    assert_eq!(&*heap.read(object(2)).unwrap().unwrap(), b"object");
+
+    drop(heap);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
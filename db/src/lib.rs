@@ -1,13 +1,20 @@
 mod config;
 mod db;
 mod fs;
+mod heap;
 mod num;
 mod object_store;
 mod util;
 
 pub use crate::config::Config;
-pub use crate::db::{open, Db, InterestFilter};
-pub use crate::util::{Bytes, WriteBatch};
+pub use crate::db::{
+    open, CacheStats, Collection, CompactionStats, Conflict, Db, InterestFilter, Log, LogIter,
+    Range, Rejected, Snapshot, Subscriber, TransactorContext, TransactorResult, Tx, WatchEvent,
+};
+pub use crate::object_store::ObjectStats;
+#[cfg(feature = "encryption")]
+pub use crate::util::Keyring;
+pub use crate::util::{Bytes, Compression, WriteBatch};
 
 const CARGO_PKG: &str = concat!(
     std::env!("CARGO_PKG_NAME"),
@@ -15,4 +22,4 @@ const CARGO_PKG: &str = concat!(
     std::env!("CARGO_PKG_VERSION"),
 );
 
-use num::{CollectionId, Lsn, VirtualStorageAddress};
+use num::{CollectionId, Lsn};
@@ -1,11 +1,69 @@
-pub(crate) struct VirtualStorageAddress {
-    pub lsn: Lsn,
-}
+use std::num::NonZeroU64;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct CollectionId {
     pub value: std::num::NonZeroU64,
 }
 
+impl CollectionId {
+    /// Maps collection names to their ids.
+    pub const CATALOG: CollectionId = CollectionId {
+        value: NonZeroU64::new(1).unwrap(),
+    };
+
+    /// The keyspace used by the plain `Tx` methods.
+    pub const DEFAULT: CollectionId = CollectionId {
+        value: NonZeroU64::new(2).unwrap(),
+    };
+
+    /// Maps object keys to the blobs that store their bytes.
+    pub const OBJECTS: CollectionId = CollectionId {
+        value: NonZeroU64::new(3).unwrap(),
+    };
+
+    /// The id assigned to the first named collection.
+    pub const FIRST_NAMED: CollectionId = CollectionId {
+        value: NonZeroU64::new(4).unwrap(),
+    };
+
+    pub fn next(self) -> CollectionId {
+        CollectionId {
+            value: self.value.checked_add(1).expect("CollectionId overflow"),
+        }
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 8] {
+        self.value.get().to_be_bytes()
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 8]) -> Option<CollectionId> {
+        NonZeroU64::new(u64::from_be_bytes(bytes)).map(|value| CollectionId { value })
+    }
+}
+
+/// Log sequence number. Every commit is stamped with an `Lsn` that is
+/// greater than that of every commit before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct Lsn {
     pub value: std::num::NonZeroU64,
 }
+
+impl Lsn {
+    pub const FIRST: Lsn = Lsn {
+        value: NonZeroU64::MIN,
+    };
+
+    pub fn next(self) -> Lsn {
+        Lsn {
+            value: self.value.checked_add(1).expect("Lsn overflow"),
+        }
+    }
+
+    pub fn to_le_bytes(self) -> [u8; 8] {
+        self.value.get().to_le_bytes()
+    }
+
+    pub fn from_le_bytes(bytes: [u8; 8]) -> Option<Lsn> {
+        NonZeroU64::new(u64::from_le_bytes(bytes)).map(|value| Lsn { value })
+    }
+}
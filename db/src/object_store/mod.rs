@@ -1,7 +1,90 @@
 mod object_store;
 
-struct ObjectId {
-    value: std::num::NonZeroU64,
+pub(crate) use object_store::ObjectStore;
+
+use std::num::NonZeroU64;
+
+use crate::Bytes;
+
+/// Identifies the immutable blob that stores the bytes of an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId {
+    pub(crate) value: NonZeroU64,
+}
+
+/// Statistics about the stored objects and the heap files that hold
+/// their bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectStats {
+    /// The number of stored blobs, including those of replaced or deleted
+    /// objects that a snapshot may still read.
+    pub blobs: u64,
+    /// The number of heap files.
+    pub heap_files: u64,
+    /// The sum of the sizes of the heap files, which includes the space
+    /// of removed blobs until defragmentation reclaims it.
+    pub heap_bytes: u64,
 }
 
-struct ObjectMetadata {}
+/// Describes the bytes of a stored object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectMetadata {
+    pub(crate) len: u64,
+    pub(crate) crc: u32,
+}
+
+impl ObjectMetadata {
+    pub(crate) fn for_bytes(bytes: &[u8]) -> ObjectMetadata {
+        ObjectMetadata {
+            len: bytes.len() as u64,
+            crc: crc32fast::hash(bytes),
+        }
+    }
+
+    /// The length of the object in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Encodes the value of an entry in the object namespace, which points
+/// at the blob holding the object's bytes.
+pub(crate) fn encode_object_ref(id: ObjectId, metadata: ObjectMetadata) -> Bytes {
+    let mut value = Vec::with_capacity(20);
+    value.extend_from_slice(&id.value.get().to_be_bytes());
+    value.extend_from_slice(&metadata.len.to_be_bytes());
+    value.extend_from_slice(&metadata.crc.to_be_bytes());
+    Bytes::from(value)
+}
+
+pub(crate) fn decode_object_ref(value: &[u8]) -> (ObjectId, ObjectMetadata) {
+    let (id, rest) = value.split_at(8);
+    let (len, crc) = rest.split_at(8);
+
+    let id = NonZeroU64::new(u64::from_be_bytes(id.try_into().unwrap()))
+        .expect("corrupt object reference");
+
+    (
+        ObjectId { value: id },
+        ObjectMetadata {
+            len: u64::from_be_bytes(len.try_into().unwrap()),
+            crc: u32::from_be_bytes(crc.try_into().expect("corrupt object reference")),
+        },
+    )
+}
+
+#[test]
+fn smoke_object_ref() {
+    let id = ObjectId {
+        value: NonZeroU64::new(u64::MAX - 1).unwrap(),
+    };
+    let metadata = ObjectMetadata::for_bytes(b"hello");
+
+    let value = encode_object_ref(id, metadata);
+    assert_eq!(value.len(), 20);
+    assert_eq!(decode_object_ref(&value), (id, metadata));
+}
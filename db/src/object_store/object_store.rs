@@ -1,17 +1,295 @@
-use crate::fs::{AsyncFs, Error, Unavailable};
+use std::collections::{BTreeSet, VecDeque};
+use std::io;
+use std::num::NonZeroU64;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-use super::{ObjectId, ObjectMetadata};
+use drama::{Classification, Executor, TenantId};
 
-pub struct ObjectStore {
-    fs: AsyncFs,
+use crate::Lsn;
+use crate::db::SnapshotLsn;
+use crate::heap::Heap;
+use crate::util::Keys;
+
+use super::{ObjectId, ObjectMetadata, ObjectStats};
+
+/// Stores the bytes of objects as immutable blobs in the [`Heap`], keyed
+/// by [`ObjectId`].
+///
+/// The blobs of a commit are written as one heap batch before the commit
+/// that references them, so a committed reference never points at a
+/// missing blob. Blobs that are written but never referenced, because
+/// their commit failed or because of a crash, are removed when the
+/// database is next opened.
+///
+/// Blobs are encrypted with the current key, if any, along with its key
+/// id, so each blob is decrypted with the key it was written with.
+pub(crate) struct ObjectStore {
+    /// Cloned for each task, as a heap is not shared between threads.
+    heap: Mutex<Heap>,
+    keys: Keys,
+    thread_pool: Executor,
+    tenant_id: TenantId,
+    next_id: AtomicU64,
+    blobs: AtomicU64,
+    /// Blobs that were replaced or deleted by the commit at `Lsn`. They
+    /// are removed once no snapshot can read them anymore.
+    garbage: Mutex<VecDeque<(Lsn, ObjectId)>>,
+    /// Set while a [`Heap::maintenance`] is running.
+    maintaining: Arc<AtomicBool>,
+}
+
+/// Blobs are copied into a checkpoint in batches of about this size.
+const COPY_BATCH_BYTES: usize = 64 * 1024 * 1024;
+
+fn missing_blob(id: ObjectId) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("object blob {:016x} is missing", id.value.get()),
+    )
 }
 
 impl ObjectStore {
-    pub async fn write_batch<I, B>(&self, objects: I) -> Result<(), Error<(Unavailable,)>>
+    /// Removes every blob in the heap that is not in `referenced`.
+    pub(crate) fn recover(
+        heap: Heap,
+        keys: Keys,
+        threads: usize,
+        tenant_id: TenantId,
+        referenced: &BTreeSet<ObjectId>,
+    ) -> io::Result<ObjectStore> {
+        let mut max_id = referenced.last().map_or(0, |id| id.value.get());
+        let mut unreferenced = vec![];
+
+        for id in heap.ids() {
+            max_id = max_id.max(id.value.get());
+
+            // Removed blobs leave a tombstone, which reads as nothing
+            // without touching the disk
+            if !referenced.contains(&id) && heap.read(id)?.is_some() {
+                unreferenced.push((id, None::<&[u8]>));
+            }
+        }
+
+        heap.write_batch(unreferenced)?;
+
+        Ok(ObjectStore {
+            heap: Mutex::new(heap),
+            keys,
+            thread_pool: Executor::new(threads),
+            tenant_id,
+            next_id: AtomicU64::new(max_id + 1),
+            blobs: AtomicU64::new(referenced.len() as u64),
+            garbage: Mutex::default(),
+            maintaining: Arc::default(),
+        })
+    }
+
+    fn heap(&self) -> Heap {
+        self.heap.lock().unwrap().clone()
+    }
+
+    pub(crate) fn allocate_id(&self) -> ObjectId {
+        let value = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        ObjectId {
+            value: NonZeroU64::new(value).expect("ObjectId overflow"),
+        }
+    }
+
+    /// Durably writes the blobs of new objects in one batch.
+    pub async fn write_batch<I>(&self, objects: I) -> io::Result<()>
     where
-        I: IntoIterator<Item = (ObjectId, ObjectMetadata, B)>,
-        B: AsRef<[u8]>,
+        I: IntoIterator<Item = (ObjectId, ObjectMetadata, Vec<u8>)>,
     {
-        todo!()
+        let batch: Vec<(ObjectId, Option<Vec<u8>>)> = objects
+            .into_iter()
+            .map(|(id, metadata, bytes)| {
+                debug_assert_eq!(metadata, ObjectMetadata::for_bytes(&bytes));
+
+                let sealed = self.keys.seal_object(&id.value.get().to_le_bytes(), bytes);
+                (id, Some(sealed))
+            })
+            .collect();
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let written = batch.len() as u64;
+        let heap = self.heap();
+
+        self.thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                heap.write_batch(batch)
+            })
+            .await
+            .expect("heap thread crashed")?;
+
+        self.blobs.fetch_add(written, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Copies the blobs of `ids` as they are stored, still encrypted if
+    /// they were, into a new heap in `directory`.
+    pub(crate) fn copy_to(&self, directory: &Path, ids: &BTreeSet<ObjectId>) -> io::Result<()> {
+        let heap = self.heap();
+        let copy = Heap::open(directory)?;
+
+        let mut batch = vec![];
+        let mut batch_bytes = 0;
+
+        for &id in ids {
+            let buf = heap.read(id)?.ok_or_else(|| missing_blob(id))?;
+
+            batch_bytes += buf.len();
+            batch.push((id, Some(buf)));
+
+            // bounds the memory held by the copy
+            if batch_bytes >= COPY_BATCH_BYTES {
+                copy.write_batch(std::mem::take(&mut batch))?;
+                batch_bytes = 0;
+            }
+        }
+
+        copy.write_batch(batch)
     }
+
+    pub(crate) async fn read(&self, id: ObjectId, metadata: ObjectMetadata) -> io::Result<Vec<u8>> {
+        let heap = self.heap();
+
+        let buf = self
+            .thread_pool
+            .spawn(self.tenant_id, Classification::Read, move || heap.read(id))
+            .await
+            .expect("heap thread crashed")?
+            .ok_or_else(|| missing_blob(id))?;
+
+        let buf =
+            self.keys
+                .open_object(&id.value.get().to_le_bytes(), buf.into_vec(), metadata.len)?;
+
+        if buf.len() as u64 != metadata.len || crc32fast::hash(&buf) != metadata.crc {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("object blob {:016x} failed its crc check", id.value.get()),
+            ));
+        }
+
+        Ok(buf)
+    }
+
+    /// Removes blobs that were never referenced by a commit.
+    pub(crate) async fn delete_unreferenced(&self, ids: &[ObjectId]) {
+        let batch: Vec<(ObjectId, Option<&[u8]>)> = ids.iter().map(|id| (*id, None)).collect();
+        let heap = self.heap();
+
+        let result = self
+            .thread_pool
+            .spawn(self.tenant_id, Classification::Write, move || {
+                heap.write_batch(batch)
+            })
+            .await
+            .expect("heap thread crashed");
+
+        // Blobs that fail to be removed now are removed on recovery
+        if result.is_ok() {
+            self.blobs.fetch_sub(ids.len() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Schedules the blob of an object that was replaced or deleted by the
+    /// commit at `lsn` to be removed.
+    pub(crate) fn defer_delete(&self, lsn: Lsn, id: ObjectId) {
+        self.garbage.lock().unwrap().push_back((lsn, id));
+    }
+
+    /// Removes the blobs that no snapshot at or after `horizon` can read.
+    pub(crate) async fn collect_garbage(&self, horizon: SnapshotLsn) {
+        let mut collectable = vec![];
+
+        {
+            let mut garbage = self.garbage.lock().unwrap();
+            while let Some((lsn, id)) = garbage.front() {
+                if Some(*lsn) > horizon {
+                    break;
+                }
+                collectable.push(*id);
+                garbage.pop_front();
+            }
+        }
+
+        if collectable.is_empty() {
+            return;
+        }
+
+        self.delete_unreferenced(&collectable).await;
+
+        // Reclaim the space of the removed blobs in the background, once
+        // enough of the heap is dead and no other maintenance is running.
+        // The result is received by no one, as a failed defragmentation
+        // leaves the heap files as they were.
+        let heap = self.heap();
+        if !heap.needs_maintenance() || self.maintaining.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let maintaining = self.maintaining.clone();
+        drop(
+            self.thread_pool
+                .spawn(self.tenant_id, Classification::Write, move || {
+                    let _ = heap.maintenance();
+                    maintaining.store(false, Ordering::Release);
+                }),
+        );
+    }
+
+    pub(crate) fn stats(&self) -> ObjectStats {
+        let (heap_files, heap_bytes) = self.heap().file_stats();
+
+        ObjectStats {
+            blobs: self.blobs.load(Ordering::Relaxed),
+            heap_files,
+            heap_bytes,
+        }
+    }
+}
+
+#[test]
+fn smoke_object_store_recover() {
+    let directory = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let committed = ObjectId {
+        value: NonZeroU64::new(1).unwrap(),
+    };
+    let orphan = ObjectId {
+        value: NonZeroU64::new(2).unwrap(),
+    };
+
+    // a blob whose commit crashed before it was logged
+    Heap::open(&directory)
+        .unwrap()
+        .write_batch([(committed, Some(b"1")), (orphan, Some(b"2"))])
+        .unwrap();
+
+    let referenced = BTreeSet::from([committed]);
+    let heap = Heap::open(&directory).unwrap();
+    let store = ObjectStore::recover(
+        heap.clone(),
+        Keys::default(),
+        1,
+        TenantId::new(&directory),
+        &referenced,
+    )
+    .unwrap();
+
+    assert_eq!(store.stats().blobs, 1);
+    assert!(heap.read(committed).unwrap().is_some());
+    assert!(heap.read(orphan).unwrap().is_none());
+    assert_eq!(store.allocate_id().value.get(), 3);
+
+    drop((store, heap));
+    std::fs::remove_dir_all(&directory).unwrap();
 }
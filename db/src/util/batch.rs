@@ -2,7 +2,8 @@ use std::io;
 
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 
-use super::{read_frame, write_frame};
+use super::encryption::FileCipher;
+use super::{Compression, RecordType, read_frame, write_frame};
 
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, FromBytes, KnownLayout, Immutable, IntoBytes, Unaligned,
@@ -12,7 +13,14 @@ struct BatchHeader {
     sub_frames: [u8; 8],
 }
 
-pub fn write_batch<B, Buf, W>(batch: B, w: W) -> io::Result<()>
+pub fn write_batch<B, Buf, W>(
+    record_type: RecordType,
+    compression: Compression,
+    cipher: &FileCipher,
+    offset: u64,
+    batch: B,
+    w: W,
+) -> io::Result<()>
 where
     W: io::Write,
     B: ExactSizeIterator<Item = Buf>,
@@ -32,13 +40,24 @@ where
         out.extend_from_slice(&buf);
     }
 
-    write_frame(&out, w)
+    write_frame(record_type, compression, cipher, offset, &out, w)
 }
 
-pub fn read_batch<R: io::Read>(r: R) -> io::Result<Vec<Vec<u8>>> {
-    let frame = read_frame(r)?;
+/// Reads a batch from the frame at `offset`, which must hold an
+/// `expected` record.
+pub fn read_batch(
+    expected: RecordType,
+    cipher: &FileCipher,
+    offset: u64,
+    r: &mut &[u8],
+) -> io::Result<Vec<Vec<u8>>> {
+    parse_batch(&read_frame(expected, cipher, offset, r)?)
+}
 
-    let (header, mut rest) = BatchHeader::ref_from_prefix(&frame).unwrap();
+/// Splits the body of a frame written by [`write_batch`] into its
+/// sub-frames.
+pub fn parse_batch(frame: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let (header, mut rest) = BatchHeader::ref_from_prefix(frame).unwrap();
 
     let sub_frames: usize = u64::from_le_bytes(header.sub_frames).try_into().unwrap();
 
@@ -82,7 +101,15 @@ fn smoke_batch() {
             batch.push(buf);
         }
 
-        write_batch(batch.iter(), &mut frame_buf).unwrap();
+        write_batch(
+            RecordType::TableMeta,
+            Compression::None,
+            &FileCipher::default(),
+            0,
+            batch.iter(),
+            &mut frame_buf,
+        )
+        .unwrap();
         expected_batches.push(batch);
     }
 
@@ -91,10 +118,24 @@ fn smoke_batch() {
     let mut read_slice = &frame_buf[..];
 
     for _ in 0..N {
-        let next_batch = read_batch(&mut read_slice).unwrap();
+        let next_batch = read_batch(
+            RecordType::TableMeta,
+            &FileCipher::default(),
+            0,
+            &mut read_slice,
+        )
+        .unwrap();
         let expected_next = expected_batches.pop().unwrap();
         assert_eq!(next_batch, expected_next);
     }
 
-    assert!(read_frame(&mut read_slice).is_err());
+    assert!(
+        read_frame(
+            RecordType::TableMeta,
+            &FileCipher::default(),
+            0,
+            &mut read_slice
+        )
+        .is_err()
+    );
 }
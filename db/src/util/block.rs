@@ -1,8 +1,10 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
 use std::marker::PhantomData;
 use std::mem;
+use std::ops::{Bound, RangeBounds};
 
-use fst::{Map, MapBuilder};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 
 #[derive(Clone)]
@@ -95,6 +97,11 @@ where
 
         // early-exit if our key map doesn't contain what we're looking for
         let value_index_u64: u64 = map.get(key.as_ref())?;
+
+        Some(self.value_at(value_index_u64))
+    }
+
+    fn value_at(&self, value_index_u64: u64) -> &V {
         let value_index: usize = value_index_u64.try_into().unwrap();
 
         let value_start = value_index * mem::size_of::<V>();
@@ -102,12 +109,281 @@ where
 
         let value_bytes = &self.value_array()[value_start..value_end];
 
-        let value = V::ref_from_bytes(value_bytes).unwrap();
+        V::ref_from_bytes(value_bytes).unwrap()
+    }
 
-        Some(value)
+    /// Iterates over the keys within `range` in order, along with their
+    /// values. The iterator can also be run in reverse.
+    pub fn range<K, R>(&self, range: R) -> Iter<'_, Self>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        Iter::new(self, &self.map(), range)
+    }
+
+    pub fn iter(&self) -> Iter<'_, Self> {
+        self.range::<[u8], _>(..)
     }
 }
 
+/// A block mapping keys to variable-length byte values, for values that
+/// can't be stored in the fixed-stride array of a [`Block`].
+///
+/// Serializes the map as:
+/// [BytesHeader, Fst from key to index, array of value offsets, value heap]
+///
+/// The offset array holds one more entry than there are keys, so the
+/// value at index `i` spans `offsets[i]..offsets[i + 1]` of the heap.
+#[derive(Clone)]
+pub struct BytesBlock {
+    buf: Vec<u8>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromBytes, KnownLayout, Immutable, IntoBytes, Unaligned,
+)]
+#[repr(C)]
+struct BytesHeader {
+    offsets_start_offset: [u8; 8],
+    heap_start_offset: [u8; 8],
+}
+
+impl BytesHeader {
+    fn offsets_start_offset(&self) -> usize {
+        usize::try_from(u64::from_le_bytes(self.offsets_start_offset)).unwrap()
+    }
+
+    fn heap_start_offset(&self) -> usize {
+        usize::try_from(u64::from_le_bytes(self.heap_start_offset)).unwrap()
+    }
+}
+
+const OFFSET_LEN: usize = mem::size_of::<u64>();
+
+/// Build a BytesBlock from a sorted map of byte-viewable keys and values.
+impl<K, V> From<&BTreeMap<K, V>> for BytesBlock
+where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    fn from(map: &BTreeMap<K, V>) -> BytesBlock {
+        let mut buf = vec![0; mem::size_of::<BytesHeader>()];
+        let mut map_builder = MapBuilder::new(&mut buf).unwrap();
+        let mut offsets: Vec<u8> = vec![];
+        let mut heap: Vec<u8> = vec![];
+
+        for (i, (k, v)) in map.iter().enumerate() {
+            offsets.extend_from_slice(&(heap.len() as u64).to_le_bytes());
+            heap.extend_from_slice(v.as_ref());
+            map_builder.insert(k.as_ref(), i as u64).unwrap();
+        }
+        offsets.extend_from_slice(&(heap.len() as u64).to_le_bytes());
+
+        map_builder.finish().unwrap();
+
+        let offsets_start_offset = buf.len() as u64;
+        let heap_start_offset = offsets_start_offset + offsets.len() as u64;
+
+        let header = BytesHeader {
+            offsets_start_offset: offsets_start_offset.to_le_bytes(),
+            heap_start_offset: heap_start_offset.to_le_bytes(),
+        };
+        buf[0..mem::size_of::<BytesHeader>()].copy_from_slice(header.as_bytes());
+
+        buf.append(&mut offsets);
+        buf.append(&mut heap);
+
+        BytesBlock { buf }
+    }
+}
+
+impl BytesBlock {
+    fn header(&self) -> &BytesHeader {
+        let header_len = size_of::<BytesHeader>();
+
+        BytesHeader::ref_from_bytes(&self.buf[..header_len]).unwrap()
+    }
+
+    fn map(&self) -> Map<&[u8]> {
+        let header_len = size_of::<BytesHeader>();
+        let header = self.header();
+        Map::new(&self.buf[header_len..header.offsets_start_offset()]).unwrap()
+    }
+
+    fn offset_at(&self, index: usize) -> usize {
+        let start = self.header().offsets_start_offset() + index * OFFSET_LEN;
+        let offset_bytes: [u8; OFFSET_LEN] =
+            self.buf[start..start + OFFSET_LEN].try_into().unwrap();
+        usize::try_from(u64::from_le_bytes(offset_bytes)).unwrap()
+    }
+
+    fn value_at(&self, value_index_u64: u64) -> &[u8] {
+        let value_index: usize = value_index_u64.try_into().unwrap();
+
+        let heap = &self.buf[self.header().heap_start_offset()..];
+
+        &heap[self.offset_at(value_index)..self.offset_at(value_index + 1)]
+    }
+
+    pub fn get<K>(&self, key: K) -> Option<&[u8]>
+    where
+        K: AsRef<[u8]>,
+    {
+        let value_index_u64: u64 = self.map().get(key.as_ref())?;
+
+        Some(self.value_at(value_index_u64))
+    }
+
+    /// Iterates over the keys within `range` in order, along with their
+    /// values. The iterator can also be run in reverse.
+    pub fn range<K, R>(&self, range: R) -> Iter<'_, Self>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        Iter::new(self, &self.map(), range)
+    }
+
+    pub fn iter(&self) -> Iter<'_, Self> {
+        self.range::<[u8], _>(..)
+    }
+
+    /// The serialized form of this block, which can be read back with
+    /// [`BytesBlock::from_bytes`].
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub(crate) fn from_bytes(buf: Vec<u8>) -> io::Result<BytesBlock> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "corrupt block");
+
+        let header_len = size_of::<BytesHeader>();
+        let (header, _) = BytesHeader::ref_from_prefix(&buf).map_err(|_| invalid())?;
+        let offsets_start_offset = header.offsets_start_offset();
+        let heap_start_offset = header.heap_start_offset();
+
+        if offsets_start_offset < header_len
+            || heap_start_offset < offsets_start_offset
+            || heap_start_offset > buf.len()
+        {
+            return Err(invalid());
+        }
+
+        let map = Map::new(&buf[header_len..offsets_start_offset]).map_err(|_| invalid())?;
+
+        if heap_start_offset - offsets_start_offset != (map.len() + 1) * OFFSET_LEN {
+            return Err(invalid());
+        }
+
+        // offsets must ascend and end at the end of the heap, so that
+        // every value lies within it
+        let heap_len = buf.len() - heap_start_offset;
+        let mut previous = 0;
+        for offset_bytes in buf[offsets_start_offset..heap_start_offset].chunks_exact(OFFSET_LEN) {
+            let offset = u64::from_le_bytes(offset_bytes.try_into().unwrap());
+            if offset < previous || offset > heap_len as u64 {
+                return Err(invalid());
+            }
+            previous = offset;
+        }
+        if previous != heap_len as u64 {
+            return Err(invalid());
+        }
+
+        Ok(BytesBlock { buf })
+    }
+}
+
+/// Looks up the value stored at an index of a block's FST, so that the
+/// iterator can be shared by both kinds of block.
+pub trait BlockValues {
+    type Value: ?Sized;
+
+    fn value_at(&self, value_index: u64) -> &Self::Value;
+}
+
+impl<V> BlockValues for Block<V>
+where
+    V: Immutable + IntoBytes + KnownLayout + FromBytes + Unaligned,
+{
+    type Value = V;
+
+    fn value_at(&self, value_index: u64) -> &V {
+        Block::value_at(self, value_index)
+    }
+}
+
+impl BlockValues for BytesBlock {
+    type Value = [u8];
+
+    fn value_at(&self, value_index: u64) -> &[u8] {
+        BytesBlock::value_at(self, value_index)
+    }
+}
+
+/// An ordered iterator over the entries of a block within a range.
+///
+/// FST streams only run forward, so the keys within the range are
+/// collected up front, which is cheap for a single block and lets the
+/// iterator run from either end.
+pub struct Iter<'a, B: ?Sized> {
+    block: &'a B,
+    entries: VecDeque<(Vec<u8>, u64)>,
+}
+
+impl<'a, B: BlockValues> Iter<'a, B> {
+    fn new<K, R>(block: &'a B, map: &Map<&[u8]>, range: R) -> Iter<'a, B>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let mut builder = map.range();
+
+        builder = match range.start_bound() {
+            Bound::Included(lo) => builder.ge(lo.as_ref()),
+            Bound::Excluded(lo) => builder.gt(lo.as_ref()),
+            Bound::Unbounded => builder,
+        };
+
+        builder = match range.end_bound() {
+            Bound::Included(hi) => builder.le(hi.as_ref()),
+            Bound::Excluded(hi) => builder.lt(hi.as_ref()),
+            Bound::Unbounded => builder,
+        };
+
+        let mut stream = builder.into_stream();
+        let mut entries = VecDeque::new();
+        while let Some((key, value_index)) = stream.next() {
+            entries.push_back((key.to_vec(), value_index));
+        }
+
+        Iter { block, entries }
+    }
+}
+
+impl<'a, B: BlockValues> Iterator for Iter<'a, B> {
+    type Item = (Vec<u8>, &'a B::Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value_index) = self.entries.pop_front()?;
+        Some((key, self.block.value_at(value_index)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.entries.len(), Some(self.entries.len()))
+    }
+}
+
+impl<B: BlockValues> DoubleEndedIterator for Iter<'_, B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value_index) = self.entries.pop_back()?;
+        Some((key, self.block.value_at(value_index)))
+    }
+}
+
+impl<B: BlockValues> ExactSizeIterator for Iter<'_, B> {}
+
 #[test]
 fn smoke_fst_map() {
     use rand::{rng, Rng};
@@ -146,3 +422,92 @@ fn smoke_fst_map() {
     let wps = (N_TESTS * TEST_SIZE) as u128 * 1000 / before.elapsed().as_millis();
     dbg!(wps);
 }
+
+#[test]
+fn smoke_bytes_block() {
+    use rand::{Rng, rng};
+
+    const TEST_SIZE: usize = 1024;
+
+    let mut rng = rng();
+
+    let model: BTreeMap<Vec<u8>, Vec<u8>> = (0..TEST_SIZE)
+        .map(|_| {
+            let k: u64 = rng.random();
+            let mut v = vec![0; rng.random_range(0..64)];
+            rng.fill(&mut v[..]);
+            (k.to_be_bytes().to_vec(), v)
+        })
+        .collect();
+
+    let block = BytesBlock::from(&model);
+    let block = BytesBlock::from_bytes(block.as_bytes().to_vec()).unwrap();
+
+    for (k_a, v_a) in &model {
+        assert_eq!(block.get(k_a).unwrap(), &v_a[..]);
+    }
+
+    assert!(block.get([0; 3]).is_none());
+
+    let (first_k, first_v) = model.iter().next().unwrap();
+    assert_eq!(block.iter().next(), Some((first_k.clone(), &first_v[..])));
+
+    // a truncated heap is rejected rather than read out of bounds
+    let mut truncated = block.as_bytes().to_vec();
+    truncated.pop();
+    assert!(model.values().all(|v| v.is_empty()) || BytesBlock::from_bytes(truncated).is_err());
+}
+
+#[test]
+fn smoke_block_range() {
+    let model: BTreeMap<Vec<u8>, Vec<u8>> = (0_u8..=255)
+        .map(|i| (vec![i / 16, i % 16], vec![i; i as usize % 5]))
+        .collect();
+
+    let block = BytesBlock::from(&model);
+
+    assert_eq!(block.iter().len(), model.len());
+    assert_eq!(block.iter().next().unwrap().0, vec![0, 0]);
+    assert_eq!(block.iter().next_back().unwrap().0, vec![15, 15]);
+
+    let collect = |iter: Iter<'_, BytesBlock>| -> Vec<(Vec<u8>, Vec<u8>)> {
+        iter.map(|(k, v)| (k, v.to_vec())).collect()
+    };
+
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = model
+        .range(vec![3, 4]..vec![5, 0])
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    assert_eq!(collect(block.range(&[3, 4][..]..&[5, 0][..])), expected);
+
+    let mut reversed = expected.clone();
+    reversed.reverse();
+    assert_eq!(
+        block
+            .range(&[3, 4][..]..&[5, 0][..])
+            .rev()
+            .map(|(k, v)| (k, v.to_vec()))
+            .collect::<Vec<_>>(),
+        reversed
+    );
+
+    let prefixed = collect(block.range(&[7][..]..&[8][..]));
+    assert_eq!(prefixed.len(), 16);
+    assert!(prefixed.iter().all(|(k, _)| k[0] == 7));
+
+    let fixed: Block<Header> = Block::from(
+        &[(vec![0xFF], 1_u64), (vec![0xFF, 0xFF], 2), (vec![0xFE], 3)]
+            .into_iter()
+            .map(|(k, v)| {
+                let header = Header {
+                    value_start_offset: u64::to_le_bytes(v),
+                };
+                (k, header)
+            })
+            .collect::<BTreeMap<_, _>>(),
+    );
+    assert_eq!(fixed.range(&[0xFF][..]..).count(), 2);
+    assert_eq!(fixed.iter().len(), 3);
+    assert_eq!(fixed.iter().next_back().unwrap().1.value_start_offset(), 2,);
+    assert!(fixed.range(&[0xFF, 0][..]..).next_back().unwrap().0 == vec![0xFF, 0xFF]);
+}
@@ -0,0 +1,108 @@
+use std::borrow::Cow;
+use std::io;
+
+/// How the frames written to logs and tables are compressed.
+///
+/// Each frame records the codec it was compressed with, so files written
+/// with different settings remain readable, as long as the cargo feature
+/// of every codec that was used is enabled. A frame that compression
+/// would not make smaller is stored uncompressed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Fast compression with LZ4, enabled by the `lz4` feature.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Compression with zstd at the given level, enabled by the `zstd`
+    /// feature. Level 3 is zstd's default.
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+/// The codec that a frame's body was stored with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Codec {
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+impl Codec {
+    pub(crate) fn from_u8(byte: u8) -> Option<Codec> {
+        match byte {
+            0 => Some(Codec::None),
+            1 => Some(Codec::Lz4),
+            2 => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Compresses `buf`, returning the codec it was stored with.
+pub(crate) fn compress(compression: Compression, buf: &[u8]) -> (Codec, Cow<'_, [u8]>) {
+    let compressed: Option<(Codec, Vec<u8>)> = match compression {
+        Compression::None => None,
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => Some((Codec::Lz4, lz4_flex::compress_prepend_size(buf))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd { level } => zstd::bulk::compress(buf, level)
+            .ok()
+            .map(|compressed| (Codec::Zstd, compressed)),
+    };
+
+    match compressed {
+        Some((codec, compressed)) if compressed.len() < buf.len() => {
+            (codec, Cow::Owned(compressed))
+        }
+        _ => (Codec::None, Cow::Borrowed(buf)),
+    }
+}
+
+/// Restores a body that was stored with `codec`.
+pub(crate) fn decompress(codec: Codec, stored: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    match codec {
+        Codec::None => Ok(Cow::Borrowed(stored)),
+        #[cfg(feature = "lz4")]
+        Codec::Lz4 => lz4_flex::decompress_size_prepended(stored)
+            .map(Cow::Owned)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => zstd::stream::decode_all(stored).map(Cow::Owned),
+        #[allow(unreachable_patterns)]
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("frame is compressed with {codec:?}, but its cargo feature is not enabled"),
+        )),
+    }
+}
+
+/// Every compression enabled by the cargo features of this build.
+#[cfg(test)]
+pub(crate) fn enabled_compressions() -> Vec<Compression> {
+    vec![
+        Compression::None,
+        #[cfg(feature = "lz4")]
+        Compression::Lz4,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd { level: 3 },
+    ]
+}
+
+#[test]
+fn smoke_compression() {
+    let json = br#"{"name": "value", "other": "value", "list": [1, 2, 3]}"#.repeat(20);
+    let incompressible: Vec<u8> = (0..200).map(|_| rand::random()).collect();
+
+    for compression in enabled_compressions() {
+        let (codec, stored) = compress(compression, &json);
+        assert_eq!(codec == Codec::None, compression == Compression::None);
+        assert!(codec == Codec::None || stored.len() < json.len() / 5);
+        assert_eq!(decompress(codec, &stored).unwrap(), &json[..]);
+
+        let (codec, stored) = compress(compression, &incompressible);
+        assert_eq!(codec, Codec::None);
+        assert_eq!(stored, &incompressible[..]);
+    }
+}
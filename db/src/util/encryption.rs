@@ -0,0 +1,330 @@
+use std::borrow::Cow;
+use std::io;
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::aead::{Aead, Payload};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{Key, KeyInit, XChaCha20Poly1305, XNonce};
+
+use super::file_header::HeaderFields;
+use crate::Config;
+
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 24;
+#[cfg(feature = "encryption")]
+const TAG_LEN: usize = 16;
+
+/// The bytes that sealing adds to an object: its key id, nonce and tag.
+#[cfg(feature = "encryption")]
+const OBJECT_OVERHEAD: usize = 4 + NONCE_LEN + TAG_LEN;
+
+/// The keys that data at rest is encrypted with, using
+/// XChaCha20-Poly1305.
+///
+/// Each key is identified by a key id that is recorded in every file and
+/// object it encrypts, so that keys can be rotated: new data is
+/// encrypted with the current key, and previous keys are kept to read
+/// the data they encrypted until compaction rewrites it.
+///
+/// While a keyring is configured, unencrypted files and objects are
+/// refused, so that they can't be substituted for encrypted ones.
+/// Encryption is therefore enabled when a database is created.
+#[cfg(feature = "encryption")]
+#[derive(Clone)]
+pub struct Keyring {
+    current: u32,
+    keys: std::collections::BTreeMap<u32, [u8; 32]>,
+}
+
+#[cfg(feature = "encryption")]
+impl Keyring {
+    /// A keyring that encrypts with `key`, identified by `key_id`.
+    ///
+    /// # Panics
+    ///
+    /// If `key_id` is zero, which marks unencrypted files.
+    pub fn new(key_id: u32, key: [u8; 32]) -> Keyring {
+        assert_ne!(key_id, 0, "key id 0 marks unencrypted files");
+
+        Keyring {
+            current: key_id,
+            keys: [(key_id, key)].into(),
+        }
+    }
+
+    /// Keeps a key that data was previously encrypted with, so that it
+    /// can still be read.
+    pub fn with_previous_key(mut self, key_id: u32, key: [u8; 32]) -> Keyring {
+        assert_ne!(key_id, 0, "key id 0 marks unencrypted files");
+
+        self.keys.entry(key_id).or_insert(key);
+        self
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print key material
+        f.debug_struct("Keyring")
+            .field("current", &self.current)
+            .field("key_ids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// The keys of a database, from which the cipher of each file and object
+/// is chosen.
+#[derive(Clone, Default)]
+pub(crate) struct Keys {
+    #[cfg(feature = "encryption")]
+    keyring: Option<Keyring>,
+}
+
+/// Encrypts and decrypts the frames of one file with the key named by its
+/// header, or passes them through if the file is not encrypted.
+#[derive(Clone, Default)]
+pub(crate) struct FileCipher {
+    #[cfg(feature = "encryption")]
+    key: Option<(u32, XChaCha20Poly1305)>,
+    /// The id of the file, from its header.
+    file_id: [u8; 16],
+}
+
+#[cfg(feature = "encryption")]
+fn not_encrypted() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "found unencrypted data, but a keyring is configured",
+    )
+}
+
+#[cfg(feature = "encryption")]
+fn decryption_failed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "failed to decrypt data, which was tampered with or encrypted with another key",
+    )
+}
+
+impl Keys {
+    pub(crate) fn new(config: &Config) -> Keys {
+        #[cfg(not(feature = "encryption"))]
+        let _ = config;
+
+        Keys {
+            #[cfg(feature = "encryption")]
+            keyring: config.encryption.clone(),
+        }
+    }
+
+    /// The cipher of a new file, which is given a random file id.
+    pub(crate) fn for_writing(&self) -> FileCipher {
+        let mut cipher = self.for_objects();
+        cipher.file_id = rand::random();
+        cipher
+    }
+
+    /// The cipher that new objects are sealed with.
+    fn for_objects(&self) -> FileCipher {
+        #[cfg(feature = "encryption")]
+        if let Some(keyring) = &self.keyring {
+            return self.for_reading(keyring.current).unwrap();
+        }
+
+        FileCipher::default()
+    }
+
+    /// The cipher of a file with the given header.
+    pub(crate) fn for_file(&self, header: HeaderFields) -> io::Result<FileCipher> {
+        let mut cipher = self.for_reading(header.key_id)?;
+        cipher.file_id = header.file_id;
+        Ok(cipher)
+    }
+
+    /// The cipher of data encrypted with `key_id`.
+    pub(crate) fn for_reading(&self, key_id: u32) -> io::Result<FileCipher> {
+        #[cfg(feature = "encryption")]
+        if key_id == 0 && self.keyring.is_some() {
+            return Err(not_encrypted());
+        }
+
+        if key_id == 0 {
+            return Ok(FileCipher::default());
+        }
+
+        #[cfg(feature = "encryption")]
+        if let Some(key) = self
+            .keyring
+            .as_ref()
+            .and_then(|keyring| keyring.keys.get(&key_id))
+        {
+            let cipher = XChaCha20Poly1305::new(&Key::from(*key));
+            return Ok(FileCipher {
+                key: Some((key_id, cipher)),
+                file_id: [0; 16],
+            });
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "data is encrypted with key {key_id}, which is not in the configured keyring, \
+                 or the encryption feature is not enabled"
+            ),
+        ))
+    }
+
+    /// Encrypts the bytes of an object with the current key, binding them
+    /// to `aad`, or returns them as-is if encryption is disabled.
+    pub(crate) fn seal_object(&self, aad: &[u8], bytes: Vec<u8>) -> Vec<u8> {
+        let cipher = self.for_objects();
+
+        #[cfg(feature = "encryption")]
+        if let Some((key_id, _)) = &cipher.key {
+            let mut sealed = key_id.to_le_bytes().to_vec();
+            sealed.extend_from_slice(&cipher.seal(aad, Cow::Borrowed(&bytes)));
+            return sealed;
+        }
+
+        let _ = (cipher, aad);
+        bytes
+    }
+
+    /// Restores the bytes of an object that holds `len` bytes once
+    /// decrypted. Sealed objects are told apart from unencrypted ones by
+    /// their length, and bytes of any other length are returned as-is for
+    /// the caller's checks to refuse. Unencrypted objects are refused
+    /// while a keyring is configured.
+    pub(crate) fn open_object(&self, aad: &[u8], stored: Vec<u8>, len: u64) -> io::Result<Vec<u8>> {
+        #[cfg(feature = "encryption")]
+        if self.keyring.is_some() && stored.len() as u64 != len + OBJECT_OVERHEAD as u64 {
+            return Err(not_encrypted());
+        }
+
+        if stored.len() as u64 == len {
+            return Ok(stored);
+        }
+
+        #[cfg(feature = "encryption")]
+        if stored.len() as u64 == len + OBJECT_OVERHEAD as u64 {
+            let (key_id, sealed) = stored.split_at(4);
+            let key_id = u32::from_le_bytes(key_id.try_into().unwrap());
+
+            return self
+                .for_reading(key_id)?
+                .open(aad, Cow::Borrowed(sealed))
+                .map(Cow::into_owned);
+        }
+
+        let _ = aad;
+        Ok(stored)
+    }
+}
+
+impl FileCipher {
+    /// The key id to record in the header of the file, which is zero if
+    /// it is not encrypted.
+    pub(crate) fn key_id(&self) -> u32 {
+        #[cfg(feature = "encryption")]
+        if let Some((key_id, _)) = &self.key {
+            return *key_id;
+        }
+
+        0
+    }
+
+    /// The id of the file, to record in its header.
+    pub(crate) fn file_id(&self) -> [u8; 16] {
+        self.file_id
+    }
+
+    /// Encrypts `buf`, binding it to `aad`, as a random nonce followed by
+    /// the ciphertext and its tag.
+    pub(crate) fn seal<'a>(&self, aad: &[u8], buf: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        #[cfg(feature = "encryption")]
+        if let Some((_, cipher)) = &self.key {
+            let nonce: [u8; NONCE_LEN] = rand::random();
+            let payload = Payload { msg: &buf, aad };
+
+            let mut sealed = nonce.to_vec();
+            sealed.extend(
+                cipher
+                    .encrypt(&XNonce::from(nonce), payload)
+                    .expect("buffer is too long to encrypt"),
+            );
+            return Cow::Owned(sealed);
+        }
+
+        let _ = aad;
+        buf
+    }
+
+    /// Decrypts what [`FileCipher::seal`] returned for the same `aad`.
+    pub(crate) fn open<'a>(&self, aad: &[u8], stored: Cow<'a, [u8]>) -> io::Result<Cow<'a, [u8]>> {
+        #[cfg(feature = "encryption")]
+        if let Some((_, cipher)) = &self.key {
+            if stored.len() < NONCE_LEN + TAG_LEN {
+                return Err(decryption_failed());
+            }
+
+            let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+            let nonce = XNonce::try_from(nonce).unwrap();
+            let payload = Payload {
+                msg: ciphertext,
+                aad,
+            };
+
+            return cipher
+                .decrypt(&nonce, payload)
+                .map(Cow::Owned)
+                .map_err(|_| decryption_failed());
+        }
+
+        let _ = aad;
+        Ok(stored)
+    }
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn smoke_encryption() {
+    let mut config = Config::new("unused");
+    config.encryption = Some(Keyring::new(2, [2; 32]).with_previous_key(1, [1; 32]));
+    let keys = Keys::new(&config);
+
+    let cipher = keys.for_writing();
+    assert_eq!(cipher.key_id(), 2);
+
+    let sealed = cipher.seal(b"aad", Cow::Borrowed(b"secret"));
+    assert!(!sealed.windows(6).any(|window| window == b"secret"));
+    assert_eq!(cipher.open(b"aad", sealed.clone()).unwrap(), &b"secret"[..]);
+    assert!(cipher.open(b"other aad", sealed.clone()).is_err());
+
+    // data encrypted with a previous key is read with it
+    let previous = keys.for_reading(1).unwrap();
+    assert!(previous.open(b"aad", sealed.clone()).is_err());
+    let sealed = previous.seal(b"aad", Cow::Borrowed(b"secret"));
+    assert_eq!(
+        keys.for_reading(1).unwrap().open(b"aad", sealed).unwrap(),
+        &b"secret"[..]
+    );
+    assert!(keys.for_reading(3).is_err());
+
+    // unencrypted files and objects are refused
+    let error = keys.for_reading(0).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let error = keys
+        .open_object(b"id", b"object bytes".to_vec(), 12)
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(Keys::default().for_reading(0).unwrap().key_id(), 0);
+
+    let object = keys.seal_object(b"id", b"object bytes".to_vec());
+    assert_eq!(object.len(), 12 + OBJECT_OVERHEAD);
+    assert_eq!(
+        keys.open_object(b"id", object.clone(), 12).unwrap(),
+        b"object bytes"
+    );
+    assert!(keys.open_object(b"other id", object, 12).is_err());
+}
@@ -0,0 +1,191 @@
+use std::io;
+
+use crc32fast::hash;
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
+
+use super::encryption::FileCipher;
+use crate::CARGO_PKG;
+
+const MAGIC: [u8; 8] = *b"komoradb";
+
+/// The version of the on-disk format written by this build. It changes
+/// whenever a file written by this build can't be read by older builds.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+/// The start of the header, which every format version must keep, so that
+/// the version of a file is known before the rest of its header is read.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromBytes, KnownLayout, Immutable, IntoBytes, Unaligned,
+)]
+#[repr(C)]
+struct HeaderPrefix {
+    magic: [u8; 8],
+    format_version: [u8; 4],
+    /// The `CARGO_PKG` of the build that created the file, zero-padded.
+    created_by: [u8; 32],
+}
+
+/// Every file written by the database starts with this header, so that
+/// other files are refused, and files written in a newer format are
+/// refused with a clear error rather than misread.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromBytes, KnownLayout, Immutable, IntoBytes, Unaligned,
+)]
+#[repr(C)]
+struct FileHeader {
+    prefix: HeaderPrefix,
+    /// The id of the key that the frames of the file are encrypted with,
+    /// or zero if they are not encrypted.
+    key_id: [u8; 4],
+    /// Chosen at random when the file is created. Encrypted frames are
+    /// bound to it, so that they can't be moved into another file.
+    file_id: [u8; 16],
+    /// Covers the fields before it.
+    crc: [u8; 4],
+}
+
+pub(crate) const FILE_HEADER_LEN: usize = std::mem::size_of::<FileHeader>();
+
+/// A file written in a format version that this build can't read.
+#[derive(Debug, Clone)]
+pub struct UnsupportedVersion {
+    format_version: u32,
+    created_by: String,
+}
+
+impl std::fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "file was written in format version {} by {}, but {} only reads format version {}",
+            self.format_version, self.created_by, CARGO_PKG, FORMAT_VERSION,
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedVersion {}
+
+/// What the header of a file says about how its frames are laid out and
+/// encrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HeaderFields {
+    /// The id of the key that the frames are encrypted with, or zero if
+    /// they are not encrypted.
+    pub(crate) key_id: u32,
+    pub(crate) file_id: [u8; 16],
+}
+
+fn file_header(format_version: u32, key_id: u32, file_id: [u8; 16]) -> FileHeader {
+    let mut created_by = [0; 32];
+    let len = CARGO_PKG.len().min(created_by.len());
+    created_by[..len].copy_from_slice(&CARGO_PKG.as_bytes()[..len]);
+
+    let mut header = FileHeader {
+        prefix: HeaderPrefix {
+            magic: MAGIC,
+            format_version: format_version.to_le_bytes(),
+            created_by,
+        },
+        key_id: key_id.to_le_bytes(),
+        file_id,
+        crc: [0; 4],
+    };
+    header.crc = hash(&header.as_bytes()[..FILE_HEADER_LEN - 4]).to_le_bytes();
+
+    header
+}
+
+/// Writes the header of a file whose frames are encrypted with `cipher`.
+pub(crate) fn write_file_header<W: io::Write>(cipher: &FileCipher, mut w: W) -> io::Result<()> {
+    w.write_all(file_header(FORMAT_VERSION, cipher.key_id(), cipher.file_id()).as_bytes())
+}
+
+/// Checks the header at the start of `buf`, which holds a file of the
+/// given `kind`, returning how the frames of the file are encrypted.
+pub(crate) fn check_file_header(buf: &[u8], kind: &str) -> io::Result<HeaderFields> {
+    let invalid =
+        |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{kind} file {msg}"));
+
+    let Ok((prefix, _)) = HeaderPrefix::ref_from_prefix(buf) else {
+        return Err(invalid("is too short to hold a header"));
+    };
+
+    if prefix.magic != MAGIC {
+        return Err(invalid("does not start with the magic number"));
+    }
+
+    // the layout of the rest of the header depends on the version, so a
+    // newer version is refused before its checksum is looked at
+    let format_version = u32::from_le_bytes(prefix.format_version);
+
+    if format_version > FORMAT_VERSION {
+        let created_by = prefix.created_by.split(|&byte| byte == 0).next().unwrap();
+
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            UnsupportedVersion {
+                format_version,
+                created_by: String::from_utf8_lossy(created_by).into_owned(),
+            },
+        ));
+    }
+
+    if format_version != FORMAT_VERSION {
+        return Err(invalid(&format!(
+            "has an unknown format version {format_version}"
+        )));
+    }
+
+    let Ok((header, _)) = FileHeader::ref_from_prefix(buf) else {
+        return Err(invalid("is too short to hold a header"));
+    };
+
+    if hash(&header.as_bytes()[..FILE_HEADER_LEN - 4]).to_le_bytes() != header.crc {
+        return Err(invalid("has a corrupt header"));
+    }
+
+    Ok(HeaderFields {
+        key_id: u32::from_le_bytes(header.key_id),
+        file_id: header.file_id,
+    })
+}
+
+#[test]
+fn smoke_file_header() {
+    let cipher = FileCipher::default();
+    let mut buf = vec![];
+    write_file_header(&cipher, &mut buf).unwrap();
+    assert_eq!(buf.len(), FILE_HEADER_LEN);
+    assert_eq!(
+        check_file_header(&buf, "test").unwrap(),
+        HeaderFields {
+            key_id: 0,
+            file_id: cipher.file_id(),
+        }
+    );
+
+    let short = check_file_header(&buf[..FILE_HEADER_LEN - 1], "test").unwrap_err();
+    assert_eq!(short.kind(), io::ErrorKind::InvalidData);
+
+    let mut corrupt = buf.clone();
+    corrupt[20] ^= 1;
+    let error = check_file_header(&corrupt, "test").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let future = file_header(FORMAT_VERSION + 1, 0, [0; 16]);
+    let error = check_file_header(future.as_bytes(), "test").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    assert!(error.to_string().contains(CARGO_PKG));
+    assert!(
+        error
+            .to_string()
+            .contains(&format!("format version {}", FORMAT_VERSION + 1))
+    );
+
+    // a newer version may lay out the rest of its header differently, so
+    // it is refused as unsupported rather than as corrupt
+    let mut future = future.as_bytes()[..std::mem::size_of::<HeaderPrefix>()].to_vec();
+    future.extend_from_slice(&[0xff; 8]);
+    let error = check_file_header(&future, "test").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+}
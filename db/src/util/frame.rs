@@ -1,19 +1,66 @@
-use std::io;
+use std::borrow::Cow;
+use std::io::{self, Read};
 use std::panic::Location;
 
-use crc32fast::hash;
+use crc32fast::{Hasher, hash};
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 
+use super::compression::{Codec, Compression, compress, decompress};
+use super::encryption::FileCipher;
+
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, FromBytes, KnownLayout, Immutable, IntoBytes, Unaligned,
 )]
 #[repr(C)]
 struct FrameHeader {
+    /// The length of the body as stored, after compression and
+    /// encryption.
     body_len: [u8; 8],
-    body_len_crc: [u8; 4],
+    record_type: u8,
+    codec: u8,
+    /// Covers `body_len`, `record_type` and `codec`.
+    header_crc: [u8; 4],
+    /// Covers the body as stored, so that corruption is detected before
+    /// decrypting and decompressing it.
     body_crc: [u8; 4],
 }
 
+/// What a frame holds, so that a reader can tell records apart and refuse
+/// a frame that isn't the record it expects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RecordType {
+    /// A [`WriteBatch`](crate::WriteBatch) in a log segment.
+    WriteBatch = 1,
+    Manifest = 2,
+    TableBlock = 3,
+    TableMeta = 4,
+}
+
+impl RecordType {
+    fn from_u8(byte: u8) -> Option<RecordType> {
+        match byte {
+            1 => Some(RecordType::WriteBatch),
+            2 => Some(RecordType::Manifest),
+            3 => Some(RecordType::TableBlock),
+            4 => Some(RecordType::TableMeta),
+            _ => None,
+        }
+    }
+
+    /// Returns an error unless `self` is `expected`.
+    pub(crate) fn expect(self, expected: RecordType) -> io::Result<()> {
+        if self != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected a {expected:?} frame but found a {self:?} frame"),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
 #[allow(unused)]
 #[derive(Clone, Copy, Debug)]
 pub struct CrcMismatch {
@@ -32,56 +79,162 @@ impl std::fmt::Display for CrcMismatch {
 
 impl std::error::Error for CrcMismatch {}
 
-fn header_for_buf(buf: &[u8]) -> FrameHeader {
-    let body_len: [u8; 8] = (buf.len() as u64).to_le_bytes();
-    let body_len_crc: [u8; 4] = hash(&body_len).to_le_bytes();
-    let body_crc: [u8; 4] = hash(&buf).to_le_bytes();
+/// A frame that fails its checks but is followed by more of the log, so
+/// it can't be the torn tail of an interrupted append.
+#[allow(unused)]
+#[derive(Clone, Copy, Debug)]
+pub struct CorruptFrame {
+    offset: u64,
+    msg: &'static str,
+    expected_crc: u32,
+    actual_crc: u32,
+}
 
-    FrameHeader {
-        body_len,
-        body_len_crc,
-        body_crc,
+impl std::fmt::Display for CorruptFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
     }
 }
 
-pub fn write_frame<W: io::Write>(buf: &[u8], mut w: W) -> io::Result<()> {
-    let header = header_for_buf(buf);
+impl std::error::Error for CorruptFrame {}
+
+const HEADER_LEN: usize = std::mem::size_of::<FrameHeader>();
+
+fn header_crc(header: &FrameHeader) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(&header.body_len);
+    hasher.update(&[header.record_type, header.codec]);
+    hasher.finalize()
+}
+
+fn header_for_buf(record_type: RecordType, codec: Codec, buf: &[u8]) -> FrameHeader {
+    let mut header = FrameHeader {
+        body_len: (buf.len() as u64).to_le_bytes(),
+        record_type: record_type as u8,
+        codec: codec as u8,
+        header_crc: [0; 4],
+        body_crc: hash(buf).to_le_bytes(),
+    };
+    header.header_crc = header_crc(&header).to_le_bytes();
+
+    header
+}
+
+fn unknown_record_type(byte: u8) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("frame has an unknown record type {byte}"),
+    )
+}
+
+fn unknown_codec(byte: u8) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("frame has an unknown codec {byte}"),
+    )
+}
+
+/// The data authenticated along with the body of a frame stored at
+/// `offset` in the file of `cipher`. Besides the record type and codec,
+/// it binds the frame to where it is stored, so that an encrypted frame
+/// can't be moved within its file or into another one.
+fn frame_aad(cipher: &FileCipher, record_type: u8, codec: Codec, offset: u64) -> Vec<u8> {
+    let mut aad = vec![record_type, codec as u8];
+    aad.extend_from_slice(&offset.to_le_bytes());
+    aad.extend_from_slice(&cipher.file_id());
+    aad
+}
+
+/// Restores the body of a frame at `offset` that was stored with `codec`
+/// and encrypted with `cipher`.
+fn restore<'a>(
+    cipher: &FileCipher,
+    record_type: u8,
+    codec: Codec,
+    offset: u64,
+    stored: Cow<'a, [u8]>,
+) -> io::Result<Cow<'a, [u8]>> {
+    let aad = frame_aad(cipher, record_type, codec, offset);
+
+    match cipher.open(&aad, stored)? {
+        Cow::Borrowed(body) => decompress(codec, body),
+        Cow::Owned(body) => match decompress(codec, &body)? {
+            Cow::Borrowed(_) => Ok(Cow::Owned(body)),
+            Cow::Owned(decompressed) => Ok(Cow::Owned(decompressed)),
+        },
+    }
+}
+
+/// Writes a frame holding `buf`, which is compressed and then encrypted
+/// with `cipher`, to be stored at `offset` in its file. The record type,
+/// codec and offset are authenticated along with the body.
+pub fn write_frame<W: io::Write>(
+    record_type: RecordType,
+    compression: Compression,
+    cipher: &FileCipher,
+    offset: u64,
+    buf: &[u8],
+    mut w: W,
+) -> io::Result<()> {
+    let (codec, compressed) = compress(compression, buf);
+    let aad = frame_aad(cipher, record_type as u8, codec, offset);
+    let stored = cipher.seal(&aad, compressed);
+    let header = header_for_buf(record_type, codec, &stored);
     w.write_all(header.as_bytes())?;
-    w.write_all(buf)
+    w.write_all(&stored)
 }
 
+/// Reads the frame stored at `offset`, which must hold an `expected`
+/// record, from the front of `r`, a file encrypted with `cipher`.
+/// Advances `r` past the frame.
 #[track_caller]
-pub fn read_frame<R: io::Read>(mut r: R) -> io::Result<Vec<u8>> {
-    let mut header_bytes = [0_u8; std::mem::size_of::<FrameHeader>()];
+pub fn read_frame(
+    expected: RecordType,
+    cipher: &FileCipher,
+    offset: u64,
+    r: &mut &[u8],
+) -> io::Result<Vec<u8>> {
+    let mut header_bytes = [0_u8; HEADER_LEN];
 
     r.read_exact(&mut header_bytes)?;
 
     let header: &FrameHeader = FrameHeader::ref_from_bytes(&header_bytes[..]).unwrap();
+    let actual_header_crc = header_crc(header).to_le_bytes();
 
-    let actual_len_crc = hash(&header.body_len).to_le_bytes();
-
-    if actual_len_crc != header.body_len_crc {
+    if actual_header_crc != header.header_crc {
         let caller = Location::caller();
 
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
             CrcMismatch {
-                actual_crc: u32::from_le_bytes(actual_len_crc),
-                expected_crc: u32::from_le_bytes(header.body_len_crc),
+                actual_crc: u32::from_le_bytes(actual_header_crc),
+                expected_crc: u32::from_le_bytes(header.header_crc),
                 at: concat!(file!(), ':', line!()),
-                caller: caller,
-                msg: "failed crc in frame length",
+                caller,
+                msg: "failed crc in frame header",
             },
         ));
     }
 
-    let buf_len: usize = u64::from_le_bytes(header.body_len).try_into().unwrap();
-    let mut buf = Vec::with_capacity(buf_len);
+    RecordType::from_u8(header.record_type)
+        .ok_or_else(|| unknown_record_type(header.record_type))?
+        .expect(expected)?;
+
+    let codec = Codec::from_u8(header.codec).ok_or_else(|| unknown_codec(header.codec))?;
 
-    unsafe {
-        buf.set_len(buf_len);
+    // A corrupt length must not allocate more than the frame could hold
+    let body_len = u64::from_le_bytes(header.body_len);
+    if body_len > r.len() as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "frame body of {body_len} bytes is longer than the {} bytes left",
+                r.len()
+            ),
+        ));
     }
 
+    let mut buf = vec![0; body_len as usize];
     r.read_exact(&mut buf)?;
 
     let actual_body_crc = hash(&buf).to_le_bytes();
@@ -92,16 +245,200 @@ pub fn read_frame<R: io::Read>(mut r: R) -> io::Result<Vec<u8>> {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
             CrcMismatch {
-                actual_crc: u32::from_le_bytes(actual_len_crc),
-                expected_crc: u32::from_le_bytes(header.body_len_crc),
+                actual_crc: u32::from_le_bytes(actual_body_crc),
+                expected_crc: u32::from_le_bytes(header.body_crc),
                 at: concat!(file!(), ':', line!()),
-                caller: caller,
+                caller,
                 msg: "failed crc in body of frame",
             },
         ));
     }
 
-    Ok(buf)
+    restore(cipher, header.record_type, codec, offset, Cow::Owned(buf)).map(Cow::into_owned)
+}
+
+/// Iterates over the record types and bodies of the frames in a log
+/// encrypted with a cipher, starting at an offset.
+///
+/// A log that was being appended to when the machine lost power may end
+/// in a partially written frame, in which any of the pages of the
+/// interrupted append may read as zeroes, whether they hold its header or
+/// its body. A bad frame that no valid frame header follows is such a
+/// torn tail, and ends the scan without an error, leaving
+/// [`FrameScanner::valid_len`] at the end of the last valid frame, to
+/// which the log can be truncated. A bad frame followed by a valid frame
+/// header is corruption, and is returned as an `InvalidData` error
+/// holding a [`CorruptFrame`] with its offset.
+///
+/// A final frame that is complete but fails its body CRC is
+/// indistinguishable from a torn one, and is treated as torn.
+pub(crate) struct FrameScanner<'a> {
+    buf: &'a [u8],
+    cipher: FileCipher,
+    offset: usize,
+    torn: bool,
+    done: bool,
+}
+
+enum BadFrame {
+    Torn,
+    Corrupt {
+        msg: &'static str,
+        expected_crc: u32,
+        actual_crc: u32,
+    },
+}
+
+impl<'a> FrameScanner<'a> {
+    pub(crate) fn new(buf: &'a [u8], offset: usize, cipher: FileCipher) -> FrameScanner<'a> {
+        FrameScanner {
+            buf,
+            cipher,
+            offset,
+            torn: false,
+            done: false,
+        }
+    }
+
+    /// The offset just past the last valid frame returned.
+    pub(crate) fn valid_len(&self) -> usize {
+        self.offset
+    }
+
+    /// Whether the scan stopped at a torn tail rather than the end of the
+    /// log.
+    pub(crate) fn is_torn(&self) -> bool {
+        self.torn
+    }
+}
+
+/// Whether `buf` starts with a frame header that passes its CRC and
+/// whose body fits in `buf`.
+fn starts_with_frame_header(buf: &[u8]) -> bool {
+    let Ok((header, after_header)) = FrameHeader::ref_from_prefix(buf) else {
+        return false;
+    };
+
+    header_crc(header) == u32::from_le_bytes(header.header_crc)
+        && u64::from_le_bytes(header.body_len) <= after_header.len() as u64
+}
+
+/// Whether a frame header starts anywhere in `buf`, which follows a bad
+/// frame. If none does, the bad frame is the last frame of the log. Pages
+/// of zeroes never pass as a frame header.
+fn frame_header_follows(buf: &[u8]) -> bool {
+    (0..buf.len()).any(|start| starts_with_frame_header(&buf[start..]))
+}
+
+/// Checks the frame at the start of `rest`, returning its header and
+/// stored body.
+fn check_frame(rest: &[u8]) -> Result<(&FrameHeader, &[u8]), BadFrame> {
+    let Ok((header, after_header)) = FrameHeader::ref_from_prefix(rest) else {
+        return Err(BadFrame::Torn);
+    };
+
+    let actual_header_crc = header_crc(header);
+    let expected_header_crc = u32::from_le_bytes(header.header_crc);
+
+    if actual_header_crc != expected_header_crc {
+        // the length of the frame can't be trusted, so the next frame may
+        // start anywhere after this one
+        if !frame_header_follows(&rest[1..]) {
+            return Err(BadFrame::Torn);
+        }
+
+        return Err(BadFrame::Corrupt {
+            msg: "failed crc in frame header",
+            expected_crc: expected_header_crc,
+            actual_crc: actual_header_crc,
+        });
+    }
+
+    let body_len = u64::from_le_bytes(header.body_len);
+    if body_len > after_header.len() as u64 {
+        return Err(BadFrame::Torn);
+    }
+
+    let body = &after_header[..body_len as usize];
+
+    let actual_body_crc = hash(body);
+    let expected_body_crc = u32::from_le_bytes(header.body_crc);
+
+    if actual_body_crc != expected_body_crc {
+        if !frame_header_follows(&after_header[body.len()..]) {
+            return Err(BadFrame::Torn);
+        }
+
+        return Err(BadFrame::Corrupt {
+            msg: "failed crc in body of frame",
+            expected_crc: expected_body_crc,
+            actual_crc: actual_body_crc,
+        });
+    }
+
+    Ok((header, body))
+}
+
+/// Decodes the record type of a frame at `offset` that passed its
+/// checks, and restores its body.
+fn decode_frame<'a>(
+    cipher: &FileCipher,
+    offset: usize,
+    header: &FrameHeader,
+    body: &'a [u8],
+) -> io::Result<(RecordType, Cow<'a, [u8]>)> {
+    let record_type = RecordType::from_u8(header.record_type)
+        .ok_or_else(|| unknown_record_type(header.record_type))?;
+    let codec = Codec::from_u8(header.codec).ok_or_else(|| unknown_codec(header.codec))?;
+
+    let body = restore(
+        cipher,
+        header.record_type,
+        codec,
+        offset as u64,
+        Cow::Borrowed(body),
+    )?;
+
+    Ok((record_type, body))
+}
+
+impl<'a> Iterator for FrameScanner<'a> {
+    type Item = io::Result<(RecordType, Cow<'a, [u8]>)>;
+
+    fn next(&mut self) -> Option<io::Result<(RecordType, Cow<'a, [u8]>)>> {
+        if self.done || self.offset >= self.buf.len() {
+            return None;
+        }
+
+        match check_frame(&self.buf[self.offset..]) {
+            Ok((header, body)) => {
+                let offset = self.offset;
+                self.offset += HEADER_LEN + body.len();
+                Some(decode_frame(&self.cipher, offset, header, body))
+            }
+            Err(BadFrame::Torn) => {
+                self.torn = true;
+                self.done = true;
+                None
+            }
+            Err(BadFrame::Corrupt {
+                msg,
+                expected_crc,
+                actual_crc,
+            }) => {
+                self.done = true;
+                Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    CorruptFrame {
+                        offset: self.offset as u64,
+                        msg,
+                        expected_crc,
+                        actual_crc,
+                    },
+                )))
+            }
+        }
+    }
 }
 
 #[test]
@@ -120,7 +457,15 @@ fn smoke_frame() {
         let mut buf = vec![0_u8; len];
         rng.fill(&mut buf[..]);
 
-        write_frame(&buf, &mut frame_buf).unwrap();
+        write_frame(
+            RecordType::TableBlock,
+            Compression::None,
+            &FileCipher::default(),
+            0,
+            &buf,
+            &mut frame_buf,
+        )
+        .unwrap();
         expected_frames.push(buf);
     }
 
@@ -129,10 +474,222 @@ fn smoke_frame() {
     let mut read_slice = &frame_buf[..];
 
     for _ in 0..N {
-        let next = read_frame(&mut read_slice).unwrap();
+        let next = read_frame(
+            RecordType::TableBlock,
+            &FileCipher::default(),
+            0,
+            &mut read_slice,
+        )
+        .unwrap();
         let expected_next = expected_frames.pop().unwrap();
         assert_eq!(next, expected_next);
     }
 
-    assert!(read_frame(&mut read_slice).is_err());
+    assert!(
+        read_frame(
+            RecordType::TableBlock,
+            &FileCipher::default(),
+            0,
+            &mut read_slice
+        )
+        .is_err()
+    );
+
+    // a frame holding another record is refused
+    write_frame(
+        RecordType::Manifest,
+        Compression::None,
+        &FileCipher::default(),
+        0,
+        b"manifest",
+        &mut frame_buf,
+    )
+    .unwrap();
+    let mut read_slice = &frame_buf[frame_buf.len() - HEADER_LEN - 8..];
+    let error = read_frame(
+        RecordType::TableBlock,
+        &FileCipher::default(),
+        0,
+        &mut read_slice,
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    // a length beyond the end of the buffer is refused before allocating
+    let mut long = frame_buf[frame_buf.len() - HEADER_LEN - 8..].to_vec();
+    let (header, _) = FrameHeader::mut_from_prefix(&mut long).unwrap();
+    header.body_len = u64::MAX.to_le_bytes();
+    header.header_crc = header_crc(header).to_le_bytes();
+    let error = read_frame(
+        RecordType::Manifest,
+        &FileCipher::default(),
+        0,
+        &mut &long[..],
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn smoke_frame_scanner() {
+    let mut log = vec![];
+    for body in [&b"first"[..], b"second", b"third"] {
+        write_frame(
+            RecordType::WriteBatch,
+            Compression::None,
+            &FileCipher::default(),
+            log.len() as u64,
+            body,
+            &mut log,
+        )
+        .unwrap();
+    }
+    let second_offset = HEADER_LEN + 5;
+    let third_offset = second_offset + HEADER_LEN + 6;
+
+    fn scan(log: &[u8], offset: usize) -> (Vec<io::Result<Cow<'_, [u8]>>>, usize, bool) {
+        let mut scanner = FrameScanner::new(log, offset, FileCipher::default());
+        let bodies = scanner
+            .by_ref()
+            .map(|frame| frame.map(|(_, body)| body))
+            .collect();
+        (bodies, scanner.valid_len(), scanner.is_torn())
+    }
+
+    let (bodies, valid_len, torn) = scan(&log, second_offset);
+    assert_eq!(bodies.len(), 2);
+    assert_eq!(&bodies[0].as_ref().unwrap()[..], b"second");
+    assert_eq!((valid_len, torn), (log.len(), false));
+
+    // every prefix and zero-extension ends in a torn tail after the last
+    // whole frame
+    for len in third_offset + 1..log.len() {
+        for zeroes in [0, 1, 4096] {
+            let mut torn_log = log[..len].to_vec();
+            torn_log.resize(len + zeroes, 0);
+
+            let (bodies, valid_len, torn) = scan(&torn_log, 0);
+            assert_eq!(bodies.len(), 2);
+            assert!(bodies.iter().all(|body| body.is_ok()));
+            assert_eq!((valid_len, torn), (third_offset, true));
+        }
+    }
+
+    // the last frame fails its body CRC
+    let mut torn_log = log.clone();
+    *torn_log.last_mut().unwrap() ^= 1;
+    let (_, valid_len, torn) = scan(&torn_log, 0);
+    assert_eq!((valid_len, torn), (third_offset, true));
+
+    // the header of the last frame was not written, but its body was
+    let mut torn_log = log.clone();
+    torn_log[third_offset..third_offset + HEADER_LEN].fill(0);
+    let (bodies, valid_len, torn) = scan(&torn_log, 0);
+    assert!(bodies.iter().all(|body| body.is_ok()));
+    assert_eq!((valid_len, torn), (third_offset, true));
+
+    // a bad frame followed by more of the log is corruption
+    for corrupted_at in [second_offset, second_offset + HEADER_LEN] {
+        let mut corrupt_log = log.clone();
+        corrupt_log[corrupted_at] ^= 1;
+
+        let (bodies, valid_len, torn) = scan(&corrupt_log, 0);
+        assert_eq!(bodies.len(), 2);
+        let error = bodies[1].as_ref().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let corrupt: &CorruptFrame = error.get_ref().unwrap().downcast_ref().unwrap();
+        assert_eq!(corrupt.offset, second_offset as u64);
+        assert_ne!(corrupt.expected_crc, corrupt.actual_crc);
+        assert_eq!((valid_len, torn), (second_offset, false));
+    }
+}
+
+#[test]
+fn smoke_frame_compression() {
+    let body = b"a body that repeats, a body that repeats, a body that repeats".repeat(10);
+
+    let compressions = super::compression::enabled_compressions();
+
+    // a log holding frames of every codec
+    let mut log = vec![];
+    for &compression in &compressions {
+        write_frame(
+            RecordType::WriteBatch,
+            compression,
+            &FileCipher::default(),
+            log.len() as u64,
+            &body,
+            &mut log,
+        )
+        .unwrap();
+    }
+
+    let mut read_slice = &log[..];
+    for _ in &compressions {
+        assert_eq!(
+            read_frame(
+                RecordType::WriteBatch,
+                &FileCipher::default(),
+                0,
+                &mut read_slice
+            )
+            .unwrap(),
+            body
+        );
+    }
+
+    let mut scanner = FrameScanner::new(&log, 0, FileCipher::default());
+    for _ in &compressions {
+        let (record_type, scanned) = scanner.next().unwrap().unwrap();
+        assert_eq!(record_type, RecordType::WriteBatch);
+        assert_eq!(scanned, &body[..]);
+    }
+    assert!(scanner.next().is_none());
+    assert!(!scanner.is_torn());
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn smoke_frame_encryption() {
+    let mut config = crate::Config::new("unused");
+    config.encryption = Some(crate::Keyring::new(1, [1; 32]));
+    let cipher = super::encryption::Keys::new(&config).for_writing();
+
+    let body = b"a secret body";
+
+    let mut log = vec![];
+    write_frame(
+        RecordType::WriteBatch,
+        Compression::None,
+        &cipher,
+        0,
+        body,
+        &mut log,
+    )
+    .unwrap();
+    assert!(!log.windows(body.len()).any(|window| window == body));
+
+    assert_eq!(
+        read_frame(RecordType::WriteBatch, &cipher, 0, &mut &log[..]).unwrap(),
+        body
+    );
+
+    let mut scanner = FrameScanner::new(&log, 0, cipher.clone());
+    assert_eq!(scanner.next().unwrap().unwrap().1, &body[..]);
+    assert!(scanner.next().is_none());
+
+    // frames are bound to their offset and file
+    let error = read_frame(RecordType::WriteBatch, &cipher, 1, &mut &log[..]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let other_file = super::encryption::Keys::new(&config).for_writing();
+    let error = read_frame(RecordType::WriteBatch, &other_file, 0, &mut &log[..]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    // the record type is authenticated along with the body
+    let mut relabeled = log.clone();
+    let header = FrameHeader::mut_from_prefix(&mut relabeled).unwrap().0;
+    header.record_type = RecordType::Manifest as u8;
+    header.header_crc = header_crc(header).to_le_bytes();
+    let error = read_frame(RecordType::Manifest, &cipher, 0, &mut &relabeled[..]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
 }
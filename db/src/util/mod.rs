@@ -1,9 +1,24 @@
 mod batch;
 mod block;
+mod block_on;
 mod bytes;
+mod compression;
+mod encryption;
+mod file_header;
 mod frame;
 mod write_batch;
 
 pub use bytes::Bytes;
-pub use frame::{read_frame, write_frame};
+pub use compression::Compression;
+#[cfg(feature = "encryption")]
+pub use encryption::Keyring;
+pub(crate) use frame::FrameScanner;
+pub use frame::{RecordType, read_frame, write_frame};
 pub use write_batch::WriteBatch;
+
+pub(crate) use batch::{read_batch, write_batch};
+pub(crate) use block::BytesBlock;
+pub(crate) use block_on::block_on;
+pub(crate) use encryption::{FileCipher, Keys};
+pub(crate) use file_header::{FILE_HEADER_LEN, check_file_header, write_file_header};
+pub(crate) use write_batch::{deserialize_write_batch, serialize_write_batch};
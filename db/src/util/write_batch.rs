@@ -1,3 +1,143 @@
-use crate::Bytes;
+use std::io;
 
-pub type WriteBatch = std::collections::BTreeMap<Bytes, Bytes>;
+use super::batch::{parse_batch, write_batch};
+use super::encryption::FileCipher;
+use super::{Compression, RecordType};
+use crate::{Bytes, Lsn};
+
+/// A set of writes to apply atomically. A `None` value is a tombstone
+/// that removes the key.
+pub type WriteBatch = std::collections::BTreeMap<Bytes, Option<Bytes>>;
+
+const REMOVE: u8 = 0;
+const INSERT: u8 = 1;
+
+/// Serializes a [`WriteBatch`] committed at `lsn` as a single framed
+/// batch, to be stored at `offset` in its log segment. The first sub-frame is the `Lsn`, followed by alternating key
+/// and value sub-frames. Each value sub-frame begins with a tag byte
+/// distinguishing inserts from removals.
+pub(crate) fn serialize_write_batch<W: io::Write>(
+    lsn: Lsn,
+    batch: &WriteBatch,
+    compression: Compression,
+    cipher: &FileCipher,
+    offset: u64,
+    w: W,
+) -> io::Result<()> {
+    let mut sub_frames: Vec<Vec<u8>> = Vec::with_capacity(1 + batch.len() * 2);
+
+    sub_frames.push(lsn.to_le_bytes().to_vec());
+
+    for (key, value_opt) in batch {
+        sub_frames.push(key.to_vec());
+
+        let value_frame = match value_opt {
+            Some(value) => {
+                let mut frame = Vec::with_capacity(1 + value.len());
+                frame.push(INSERT);
+                frame.extend_from_slice(value);
+                frame
+            }
+            None => vec![REMOVE],
+        };
+
+        sub_frames.push(value_frame);
+    }
+
+    write_batch(
+        RecordType::WriteBatch,
+        compression,
+        cipher,
+        offset,
+        sub_frames.into_iter(),
+        w,
+    )
+}
+
+/// Deserializes the body of a frame written by [`serialize_write_batch`].
+pub(crate) fn deserialize_write_batch(frame: &[u8]) -> io::Result<(Lsn, WriteBatch)> {
+    let sub_frames = parse_batch(frame)?;
+
+    if sub_frames.len() % 2 != 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "write batch contains a key without a value",
+        ));
+    }
+
+    let mut iter = sub_frames.into_iter();
+
+    let lsn = iter
+        .next()
+        .and_then(|lsn_frame| <[u8; 8]>::try_from(lsn_frame).ok())
+        .and_then(Lsn::from_le_bytes)
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "write batch has an invalid Lsn")
+        })?;
+
+    let mut batch = WriteBatch::new();
+
+    while let (Some(key), Some(value_frame)) = (iter.next(), iter.next()) {
+        let value_opt = match value_frame.split_first() {
+            Some((&INSERT, value)) => Some(Bytes::from(value)),
+            Some((&REMOVE, [])) => None,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "write batch contains an unknown value tag",
+                ));
+            }
+        };
+
+        batch.insert(Bytes::from(key), value_opt);
+    }
+
+    Ok((lsn, batch))
+}
+
+#[test]
+fn smoke_write_batch() {
+    use super::read_frame;
+
+    let mut batch = WriteBatch::new();
+    batch.insert(Bytes::from(&b"a"[..]), Some(Bytes::from(&b"1"[..])));
+    batch.insert(Bytes::from(&b"b"[..]), Some(Bytes::from(&b""[..])));
+    batch.insert(Bytes::from(&b"c"[..]), None);
+    batch.insert(Bytes::from(&b""[..]), Some(Bytes::from(&b"3"[..])));
+
+    let mut buf = vec![];
+    serialize_write_batch(
+        Lsn::FIRST,
+        &batch,
+        Compression::None,
+        &FileCipher::default(),
+        0,
+        &mut buf,
+    )
+    .unwrap();
+    serialize_write_batch(
+        Lsn::FIRST.next(),
+        &WriteBatch::new(),
+        Compression::None,
+        &FileCipher::default(),
+        0,
+        &mut buf,
+    )
+    .unwrap();
+
+    let mut read_slice = &buf[..];
+    let mut next = || {
+        deserialize_write_batch(&read_frame(
+            RecordType::WriteBatch,
+            &FileCipher::default(),
+            0,
+            &mut read_slice,
+        )?)
+    };
+
+    assert_eq!(next().unwrap(), (Lsn::FIRST, batch));
+    let (lsn, empty) = next().unwrap();
+    assert_eq!(lsn, Lsn::FIRST.next());
+    assert!(empty.is_empty());
+    assert!(next().is_err());
+}
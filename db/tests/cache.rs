@@ -0,0 +1,56 @@
+// common contains open_tmp macro
+mod common;
+
+fn key(i: u32) -> [u8; 4] {
+    i.to_be_bytes()
+}
+
+#[test]
+fn cache_00() {
+    let path = tmp_path!();
+    let db = db::Config::new(&path).open().unwrap();
+
+    let mut tx = db.tx();
+    for i in 0..1000 {
+        tx.insert(&key(i), &[i as u8; 100]).unwrap();
+    }
+    tx.commit().unwrap();
+    db.flush().unwrap();
+
+    let snapshot = db.snapshot();
+    assert_eq!(snapshot.get(&key(7)).unwrap().unwrap(), [7; 100]);
+    let first = db.cache_stats();
+    assert!(first.misses > 0);
+    assert!(first.bytes > 0);
+
+    // the block is served from memory from now on
+    for _ in 0..10 {
+        assert_eq!(snapshot.get(&key(7)).unwrap().unwrap(), [7; 100]);
+    }
+    let stats = db.cache_stats();
+    assert_eq!(stats.misses, first.misses);
+    assert_eq!(stats.hits, first.hits + 10);
+}
+
+#[test]
+fn cache_budget() {
+    let path = tmp_path!();
+
+    let mut config = db::Config::new(&path);
+    config.block_cache_bytes = 64 * 1024;
+    let db = config.open().unwrap();
+
+    let mut tx = db.tx();
+    for i in 0..10_000 {
+        tx.insert(&key(i), &[i as u8; 100]).unwrap();
+    }
+    tx.commit().unwrap();
+    db.flush().unwrap();
+
+    let snapshot = db.snapshot();
+    assert_eq!(snapshot.range::<&[u8], _>(..).count(), 10_000);
+
+    let stats = db.cache_stats();
+    assert!(stats.evictions > 0);
+    assert!(stats.bytes <= 64 * 1024);
+}
@@ -0,0 +1,129 @@
+// common contains open_tmp macro
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[test]
+fn checkpoint_while_writing() {
+    let path = tmp_path!();
+    let dest = path.with_extension("checkpoint");
+    let _ = std::fs::remove_dir_all(&dest);
+
+    let db = db::open(&path).unwrap();
+    let done = AtomicBool::new(false);
+
+    for i in 0_u32..100 {
+        let mut tx = db.tx();
+        tx.insert(&i.to_be_bytes(), &i.to_be_bytes()).unwrap();
+        tx.commit().unwrap();
+    }
+    db.flush().unwrap();
+
+    std::thread::scope(|scope| {
+        // each commit writes the next key and an object, so a consistent
+        // checkpoint holds every key up to one of them
+        scope.spawn(|| {
+            let mut i = 100_u32;
+            while !done.load(Ordering::Acquire) {
+                let mut tx = db.tx();
+                tx.insert(&i.to_be_bytes(), &i.to_be_bytes()).unwrap();
+                tx.put_object(&i.to_be_bytes(), &[i as u8; 100]);
+                tx.commit().unwrap();
+                i += 1;
+            }
+        });
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        db.checkpoint(&dest).unwrap();
+        done.store(true, Ordering::Release);
+    });
+
+    // a checkpoint isn't written over an existing directory
+    let error = db.checkpoint(&dest).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+
+    // tables are linked rather than copied
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let table = std::fs::read_dir(&dest)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .starts_with("table.")
+            })
+            .unwrap();
+        assert_eq!(std::fs::metadata(&table).unwrap().nlink(), 2);
+    }
+
+    let checkpoint = db::open(&dest).unwrap();
+    let snapshot = checkpoint.snapshot();
+
+    let mut len = 0_u32;
+    while snapshot.get(&len.to_be_bytes()).unwrap().is_some() {
+        len += 1;
+    }
+    assert!(len >= 100);
+
+    for i in 100..len {
+        assert_eq!(
+            snapshot.get_object(&i.to_be_bytes()).unwrap().unwrap(),
+            [i as u8; 100]
+        );
+    }
+    assert_eq!(snapshot.range::<&[u8], _>(..).count(), len as usize);
+    assert_eq!(checkpoint.object_stats().blobs, u64::from(len - 100));
+
+    // the database and its checkpoint are independent
+    drop(snapshot);
+    let mut tx = checkpoint.tx();
+    tx.insert(b"checkpoint only", b"").unwrap();
+    tx.commit().unwrap();
+    assert!(db.snapshot().get(b"checkpoint only").unwrap().is_none());
+}
+
+#[test]
+fn checkpoint_to_another_directory() {
+    let path = tmp_path!();
+    let other = tmp_path!();
+    let dest = other.join("nested").join("checkpoint");
+
+    let db = db::open(&path).unwrap();
+    let mut tx = db.tx();
+    tx.insert(b"flushed", b"flushed").unwrap();
+    tx.commit().unwrap();
+    db.flush().unwrap();
+
+    // the checkpoint's directory doesn't hold the database, but its files
+    // are still linked from it
+    db.checkpoint(&dest).unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let table = std::fs::read_dir(&dest)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .starts_with("table.")
+            })
+            .unwrap();
+        assert_eq!(std::fs::metadata(&table).unwrap().nlink(), 2);
+    }
+
+    let checkpoint = db::open(&dest).unwrap();
+    assert_eq!(
+        checkpoint.snapshot().get(b"flushed").unwrap().unwrap(),
+        b"flushed"
+    );
+}
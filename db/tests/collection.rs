@@ -0,0 +1,76 @@
+// common contains open_tmp macro
+mod common;
+
+#[test]
+fn collection_00() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let users = db.open_collection("users").unwrap();
+        let emails = db.open_collection("emails").unwrap();
+        assert_eq!(db.open_collection("users").unwrap(), users);
+
+        // one transaction spanning several collections
+        let mut tx = db.tx();
+        tx.insert_in(&users, b"1", b"alice").unwrap();
+        tx.insert_in(&emails, b"alice@example.com", b"1").unwrap();
+        tx.insert(b"1", b"default").unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.tx();
+        assert_eq!(tx.get_in(&users, b"1").unwrap().unwrap(), b"alice");
+        assert!(tx.get_in(&emails, b"1").unwrap().is_none());
+        assert_eq!(tx.get(b"1").unwrap().unwrap(), b"default");
+        assert_eq!(tx.scan_prefix_in(&users, b"").count(), 1);
+        assert_eq!(tx.range::<&[u8], _>(..).count(), 1);
+    }
+
+    {
+        let db = db::open(&path).unwrap();
+
+        assert_eq!(db.collection_names().unwrap(), vec!["emails", "users"]);
+
+        let users = db.open_collection("users").unwrap();
+        assert_eq!(
+            db.snapshot().get_in(&users, b"1").unwrap().unwrap(),
+            b"alice"
+        );
+    }
+}
+
+#[test]
+fn collection_rename_and_drop() {
+    let db = open_tmp!();
+
+    let a = db.open_collection("a").unwrap();
+
+    let mut tx = db.tx();
+    tx.insert_in(&a, b"k", b"v").unwrap();
+    tx.commit().unwrap();
+
+    db.rename_collection("a", "b").unwrap();
+    assert_eq!(db.collection_names().unwrap(), vec!["b"]);
+    assert!(db.rename_collection("a", "c").is_err());
+
+    // handles survive renames
+    let mut tx = db.tx();
+    assert_eq!(tx.get_in(&a, b"k").unwrap().unwrap(), b"v");
+    drop(tx);
+
+    assert!(db.drop_collection("b").unwrap());
+    assert!(!db.drop_collection("b").unwrap());
+    assert!(db.collection_names().unwrap().is_empty());
+
+    // writing through a handle to a dropped collection fails
+    let mut tx = db.tx();
+    assert!(tx.get_in(&a, b"k").unwrap().is_none());
+    tx.insert_in(&a, b"k", b"v").unwrap();
+    assert!(tx.commit().is_err());
+
+    // a new collection with the same name starts empty
+    let b = db.open_collection("b").unwrap();
+    assert_ne!(a, b);
+    assert_eq!(db.snapshot().scan_prefix_in(&b, b"").count(), 0);
+}
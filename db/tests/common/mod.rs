@@ -1,6 +1,15 @@
+#[macro_export]
+macro_rules! tmp_path {
+    () => {{
+        let path = std::env::temp_dir().join(concat!(file!(), ':', line!()));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }};
+}
+
 #[macro_export]
 macro_rules! open_tmp {
     () => {
-        db::open(concat!(file!(), ':', line!())).unwrap()
+        db::open($crate::tmp_path!()).unwrap()
     };
 }
@@ -0,0 +1,159 @@
+// common contains open_tmp macro
+mod common;
+
+fn key(i: u32) -> [u8; 4] {
+    i.to_be_bytes()
+}
+
+fn table_files(path: &std::path::Path) -> usize {
+    std::fs::read_dir(path)
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_str().unwrap().starts_with("table.")
+        })
+        .count()
+}
+
+#[test]
+fn compaction_00() {
+    let path = tmp_path!();
+
+    {
+        let mut config = db::Config::new(&path);
+        // only compact when asked to
+        config.compaction_trigger_tables = usize::MAX;
+        let db = config.open().unwrap();
+
+        let mut tx = db.tx();
+        for i in 0..100 {
+            tx.insert(&key(i), b"1").unwrap();
+        }
+        tx.commit().unwrap();
+        db.flush().unwrap();
+
+        let mut tx = db.tx();
+        for i in 0..50 {
+            tx.insert(&key(i), b"2").unwrap();
+        }
+        tx.commit().unwrap();
+        db.flush().unwrap();
+
+        let mut tx = db.tx();
+        for i in 90..100 {
+            tx.remove(&key(i)).unwrap();
+        }
+        tx.commit().unwrap();
+        db.flush().unwrap();
+
+        let before = db.snapshot();
+
+        assert_eq!(table_files(&path), 3);
+        db.set_compaction_rate_limit(Some(1024 * 1024));
+        db.compact().unwrap();
+
+        let stats = db.compaction_stats();
+        assert_eq!(stats.compactions, 1);
+        assert_eq!(stats.tables_merged, 3);
+        assert_eq!(stats.versions_dropped, 60);
+        assert_eq!(stats.tombstones_dropped, 10);
+        assert!(stats.bytes_read > 0 && stats.bytes_written > 0);
+
+        // the snapshot keeps reading the merged table's data, and the
+        // replaced files are deleted once nothing reads them
+        assert_eq!(before.get(&key(0)).unwrap().unwrap(), b"2");
+        assert_eq!(before.get(&key(50)).unwrap().unwrap(), b"1");
+        assert!(before.get(&key(95)).unwrap().is_none());
+        drop(before);
+
+        assert_eq!(table_files(&path), 1);
+    }
+
+    let db = db::open(&path).unwrap();
+    let snapshot = db.snapshot();
+    assert_eq!(snapshot.range::<&[u8], _>(..).count(), 90);
+    assert_eq!(snapshot.get(&key(49)).unwrap().unwrap(), b"2");
+    assert_eq!(snapshot.get(&key(89)).unwrap().unwrap(), b"1");
+    assert!(snapshot.get(&key(90)).unwrap().is_none());
+}
+
+#[test]
+fn compaction_in_background() {
+    let path = tmp_path!();
+
+    let mut config = db::Config::new(&path);
+    config.flush_threshold_bytes = 1024;
+    config.compaction_trigger_tables = 2;
+    let db = config.open().unwrap();
+
+    for i in 0..200 {
+        let mut tx = db.tx();
+        tx.insert(&key(i % 20), &[i as u8; 100]).unwrap();
+        tx.commit().unwrap();
+    }
+
+    // wait for a compaction that may still be running
+    db.compact().unwrap();
+
+    assert!(db.compaction_stats().compactions > 0);
+    assert!(table_files(&path) <= 2);
+
+    let snapshot = db.snapshot();
+    for i in 180..200 {
+        assert_eq!(snapshot.get(&key(i % 20)).unwrap().unwrap(), [i as u8; 100]);
+    }
+}
+
+#[test]
+fn compaction_merges_runs_of_similar_tables() {
+    let path = tmp_path!();
+
+    let mut config = db::Config::new(&path);
+    config.compaction_trigger_tables = 2;
+    let db = config.open().unwrap();
+
+    // a large table followed by small ones
+    let mut tx = db.tx();
+    for i in 0..1000 {
+        tx.insert(&key(i), &[1; 100]).unwrap();
+    }
+    tx.commit().unwrap();
+    db.flush().unwrap();
+
+    for i in 0..2 {
+        let mut tx = db.tx();
+        tx.insert(&key(i), &[2; 100]).unwrap();
+        tx.remove(&key(999)).unwrap();
+        tx.commit().unwrap();
+        db.flush().unwrap();
+    }
+
+    // wait for the background compaction to finish
+    while db.compaction_stats().compactions == 0 || table_files(&path) > 2 {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    // the small tables were merged without rewriting the large one, and
+    // the tombstone is kept to shadow it
+    let stats = db.compaction_stats();
+    assert_eq!(stats.compactions, 1);
+    assert_eq!(stats.tables_merged, 2);
+    assert_eq!(stats.versions_dropped, 1);
+    assert_eq!(stats.tombstones_dropped, 0);
+    assert_eq!(table_files(&path), 2);
+
+    let snapshot = db.snapshot();
+    assert_eq!(snapshot.get(&key(0)).unwrap().unwrap(), [2; 100]);
+    assert_eq!(snapshot.get(&key(2)).unwrap().unwrap(), [1; 100]);
+    assert!(snapshot.get(&key(999)).unwrap().is_none());
+    assert_eq!(snapshot.range::<&[u8], _>(..).count(), 999);
+    drop(snapshot);
+
+    db.compact().unwrap();
+
+    let stats = db.compaction_stats();
+    assert_eq!(stats.compactions, 2);
+    assert_eq!(stats.tombstones_dropped, 1);
+    assert_eq!(table_files(&path), 1);
+    assert_eq!(db.snapshot().range::<&[u8], _>(..).count(), 999);
+}
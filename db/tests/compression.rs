@@ -0,0 +1,62 @@
+// common contains open_tmp macro
+mod common;
+
+use db::Compression;
+
+fn compressions() -> Vec<Compression> {
+    vec![
+        Compression::None,
+        #[cfg(feature = "lz4")]
+        Compression::Lz4,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd { level: 3 },
+    ]
+}
+
+fn json(i: usize) -> Vec<u8> {
+    format!(r#"{{"id": {i}, "name": "customer {i}", "tags": ["a", "b", "c"]}}"#).into_bytes()
+}
+
+#[test]
+fn compression_mixed_files() {
+    let path = tmp_path!();
+
+    // each reopening writes a table and log frames with another codec
+    for (round, &compression) in compressions().iter().enumerate() {
+        let mut config = db::Config::new(&path);
+        config.compression = compression;
+        config.compaction_trigger_tables = usize::MAX;
+        let db = config.open().unwrap();
+
+        for i in 0..100 {
+            let mut tx = db.tx();
+            tx.insert(format!("{round}.{i}").as_bytes(), &json(i))
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        db.flush().unwrap();
+
+        let mut tx = db.tx();
+        tx.insert(format!("{round}.log").as_bytes(), &json(round))
+            .unwrap();
+        tx.commit().unwrap();
+    }
+
+    let db = db::open(&path).unwrap();
+    let mut tx = db.tx();
+    for round in 0..compressions().len() {
+        for i in 0..100 {
+            assert_eq!(
+                tx.get(format!("{round}.{i}").as_bytes()).unwrap().unwrap(),
+                json(i)
+            );
+        }
+        assert_eq!(
+            tx.get(format!("{round}.log").as_bytes()).unwrap().unwrap(),
+            json(round)
+        );
+    }
+
+    db.compact().unwrap();
+    assert_eq!(tx.get(b"0.99").unwrap().unwrap(), json(99));
+}
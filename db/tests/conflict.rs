@@ -0,0 +1,74 @@
+// common contains open_tmp macro
+mod common;
+
+use db::{Conflict, Rejected};
+
+fn is_conflict<T: std::fmt::Debug>(
+    result: Result<T, terrors::OneOf<(Conflict, Rejected, std::io::Error)>>,
+) -> bool {
+    match result {
+        Ok(_) => false,
+        Err(e) => e.narrow::<Conflict, _>().is_ok(),
+    }
+}
+
+#[test]
+fn conflict_00() {
+    let db = open_tmp!();
+
+    let mut tx1 = db.tx();
+    let mut tx2 = db.tx();
+
+    assert!(tx1.get(b"a").unwrap().is_none());
+    tx1.insert(b"b", b"1").unwrap();
+
+    tx2.insert(b"a", b"2").unwrap();
+    tx2.commit().unwrap();
+
+    // tx1 read `a` before tx2 wrote it, so tx1 can't be serialized after tx2
+    assert!(is_conflict(tx1.commit()));
+
+    let mut tx = db.tx();
+    assert!(tx.get(b"b").unwrap().is_none());
+    assert_eq!(tx.get(b"a").unwrap().unwrap(), b"2");
+}
+
+#[test]
+fn conflict_phantom() {
+    let db = open_tmp!();
+
+    let mut tx1 = db.tx();
+    let mut tx2 = db.tx();
+
+    assert_eq!(tx1.scan_prefix(b"p").count(), 0);
+    tx1.insert(b"count", b"0").unwrap();
+
+    tx2.insert(b"p1", b"").unwrap();
+    tx2.commit().unwrap();
+
+    assert!(is_conflict(tx1.commit()));
+}
+
+#[test]
+fn conflict_disjoint() {
+    let db = open_tmp!();
+
+    let mut tx1 = db.tx();
+    let mut tx2 = db.tx();
+
+    assert!(tx1.get(b"a").unwrap().is_none());
+    tx1.insert(b"a", b"1").unwrap();
+
+    assert!(tx2.get(b"b").unwrap().is_none());
+    assert_eq!(tx2.range(&b"c"[..]..).count(), 0);
+    tx2.insert(b"b", b"2").unwrap();
+
+    tx1.commit().unwrap();
+    tx2.commit().unwrap();
+
+    // a transaction that began after a commit doesn't conflict with it
+    let mut tx3 = db.tx();
+    assert_eq!(tx3.get(b"a").unwrap().unwrap(), b"1");
+    tx3.insert(b"a", b"3").unwrap();
+    tx3.commit().unwrap();
+}
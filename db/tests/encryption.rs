@@ -0,0 +1,124 @@
+#![cfg(feature = "encryption")]
+
+// common contains open_tmp macro
+mod common;
+
+use std::path::Path;
+
+use db::Keyring;
+
+const SECRET: &[u8] = b"a value that must not be stored in plaintext";
+
+fn open(path: &Path, keyring: Option<Keyring>) -> std::io::Result<db::Db> {
+    let mut config = db::Config::new(path);
+    config.encryption = keyring;
+    config.open()
+}
+
+/// Whether any file in `directory` or its subdirectories contains
+/// `needle`.
+fn stored_in_plaintext(directory: &Path, needle: &[u8]) -> bool {
+    std::fs::read_dir(directory).unwrap().any(|entry| {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            stored_in_plaintext(&path, needle)
+        } else {
+            let buf = std::fs::read(&path).unwrap();
+            buf.windows(needle.len()).any(|window| window == needle)
+        }
+    })
+}
+
+#[test]
+fn encryption_key_rotation() {
+    let path = tmp_path!();
+
+    {
+        let db = open(&path, Some(Keyring::new(1, [1; 32]))).unwrap();
+
+        // a table, a manifest, an object blob and a log frame
+        let mut tx = db.tx();
+        tx.insert(b"flushed", SECRET).unwrap();
+        tx.put_object(b"object", SECRET);
+        tx.commit().unwrap();
+        db.flush().unwrap();
+
+        let mut tx = db.tx();
+        tx.insert(b"logged", SECRET).unwrap();
+        tx.commit().unwrap();
+    }
+
+    assert!(!stored_in_plaintext(&path, SECRET));
+    assert!(!stored_in_plaintext(&path, b"flushed"));
+
+    // the key is required to open the database
+    let error = open(&path, None).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    let error = open(&path, Some(Keyring::new(1, [2; 32]))).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    let error = open(&path, Some(Keyring::new(2, [2; 32]))).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    // after rotating to a new key, data encrypted with the previous one
+    // is still read with it
+    let rotated = || Keyring::new(2, [2; 32]).with_previous_key(1, [1; 32]);
+
+    {
+        let db = open(&path, Some(rotated())).unwrap();
+
+        let mut tx = db.tx();
+        assert_eq!(tx.get(b"flushed").unwrap().unwrap(), SECRET);
+        assert_eq!(tx.get(b"logged").unwrap().unwrap(), SECRET);
+        assert_eq!(tx.get_object(b"object").unwrap().unwrap(), SECRET);
+
+        tx.insert(b"rotated", SECRET).unwrap();
+        tx.put_object(b"rotated object", SECRET);
+        tx.commit().unwrap();
+        db.flush().unwrap();
+        db.compact().unwrap();
+    }
+
+    // the flush and compaction rewrote the log, tables and manifest with
+    // the new key, but the first object blob still needs the previous one
+    {
+        let db = open(&path, Some(Keyring::new(2, [2; 32]))).unwrap();
+        let mut tx = db.tx();
+        assert_eq!(tx.get(b"logged").unwrap().unwrap(), SECRET);
+        assert_eq!(tx.get_object(b"rotated object").unwrap().unwrap(), SECRET);
+        let error = tx.get_object(b"object").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    let db = open(&path, Some(rotated())).unwrap();
+    let mut tx = db.tx();
+    for key in [&b"flushed"[..], b"logged", b"rotated"] {
+        assert_eq!(tx.get(key).unwrap().unwrap(), SECRET);
+    }
+    assert_eq!(tx.get_object(b"rotated object").unwrap().unwrap(), SECRET);
+    assert_eq!(tx.get_object(b"object").unwrap().unwrap(), SECRET);
+    drop(tx);
+    drop(db);
+
+    assert!(!stored_in_plaintext(&path, SECRET));
+}
+
+#[test]
+fn encryption_refuses_plaintext() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+        let mut tx = db.tx();
+        tx.insert(b"plaintext", b"before").unwrap();
+        tx.put_object(b"object", b"before");
+        tx.commit().unwrap();
+        db.flush().unwrap();
+    }
+
+    // unencrypted files can't stand in for encrypted ones
+    let error = open(&path, Some(Keyring::new(1, [1; 32]))).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    let db = db::open(&path).unwrap();
+    assert_eq!(db.snapshot().get(b"plaintext").unwrap().unwrap(), b"before");
+}
@@ -0,0 +1,90 @@
+// common contains open_tmp macro
+mod common;
+
+fn entries(iter: db::LogIter<'_>) -> Vec<(u64, Vec<u8>)> {
+    iter.map(|entry| {
+        let (seq, entry) = entry.unwrap();
+        (seq, entry.to_vec())
+    })
+    .collect()
+}
+
+#[test]
+fn log_00() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let events = db.open_log("events").unwrap();
+        assert_eq!(db.open_log("events").unwrap(), events);
+
+        // appends commit atomically with other writes
+        let mut tx = db.tx();
+        tx.append(&events, b"a");
+        tx.append(&events, b"b");
+        tx.insert(b"k", b"v").unwrap();
+        assert!(tx.read_from(&events, 0).next().is_none());
+        tx.commit().unwrap();
+
+        let mut tx = db.tx();
+        tx.append(&events, b"c");
+        tx.commit().unwrap();
+
+        // an aborted transaction does not consume sequence numbers
+        let mut tx = db.tx();
+        tx.append(&events, b"dropped");
+        drop(tx);
+
+        let snapshot = db.snapshot();
+        assert_eq!(
+            entries(snapshot.read_from(&events, 0)),
+            vec![(0, b"a".to_vec()), (1, b"b".to_vec()), (2, b"c".to_vec())]
+        );
+        assert_eq!(
+            entries(snapshot.read_from(&events, 2)),
+            vec![(2, b"c".to_vec())]
+        );
+        assert_eq!(snapshot.get(b"k").unwrap().unwrap(), b"v");
+    }
+
+    {
+        let db = db::open(&path).unwrap();
+        let events = db.open_log("events").unwrap();
+
+        let mut tx = db.tx();
+        tx.truncate_log(&events, 2).unwrap();
+        tx.append(&events, b"d");
+        tx.commit().unwrap();
+
+        // sequence numbers continue after a reopen and are not reused
+        // after truncation
+        assert_eq!(
+            entries(db.snapshot().read_from(&events, 0)),
+            vec![(2, b"c".to_vec()), (3, b"d".to_vec())]
+        );
+
+        // logs and collections share a namespace of ids, not names
+        let collection = db.open_collection("events").unwrap();
+        assert_eq!(db.snapshot().scan_prefix_in(&collection, b"").count(), 0);
+    }
+}
+
+#[test]
+fn log_concurrent_appends() {
+    let db = open_tmp!();
+    let log = db.open_log("log").unwrap();
+
+    // appenders never conflict with each other
+    let mut tx1 = db.tx();
+    let mut tx2 = db.tx();
+    tx1.append(&log, b"1");
+    tx2.append(&log, b"2");
+    tx2.commit().unwrap();
+    tx1.commit().unwrap();
+
+    assert_eq!(
+        entries(db.snapshot().read_from(&log, 0)),
+        vec![(0, b"2".to_vec()), (1, b"1".to_vec())]
+    );
+}
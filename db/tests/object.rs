@@ -0,0 +1,84 @@
+// common contains open_tmp macro
+mod common;
+
+fn live_blobs(db: &db::Db) -> u64 {
+    db.object_stats().blobs
+}
+
+#[test]
+fn object_00() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        // objects commit atomically with the metadata stored next to them
+        let mut tx = db.tx();
+        tx.put_object(b"upload", b"large bytes");
+        tx.insert(b"upload-owner", b"alice").unwrap();
+        assert_eq!(tx.get_object(b"upload").unwrap().unwrap(), b"large bytes");
+        assert!(db.snapshot().get_object(b"upload").unwrap().is_none());
+        tx.commit().unwrap();
+
+        assert_eq!(live_blobs(&db), 1);
+
+        let snapshot = db.snapshot();
+        assert_eq!(
+            snapshot.get_object(b"upload").unwrap().unwrap(),
+            b"large bytes"
+        );
+        assert_eq!(
+            snapshot.object_metadata(b"upload").unwrap().unwrap().len(),
+            11
+        );
+        assert_eq!(snapshot.get(b"upload-owner").unwrap().unwrap(), b"alice");
+
+        // the replaced blob remains readable by older snapshots
+        let mut tx = db.tx();
+        tx.put_object(b"upload", b"new bytes");
+        tx.commit().unwrap();
+
+        assert_eq!(live_blobs(&db), 2);
+        assert_eq!(
+            snapshot.get_object(b"upload").unwrap().unwrap(),
+            b"large bytes"
+        );
+        drop(snapshot);
+        assert_eq!(live_blobs(&db), 1);
+    }
+
+    {
+        let db = db::open(&path).unwrap();
+
+        // the replaced blob was removed before the database was closed
+        assert_eq!(live_blobs(&db), 1);
+
+        let mut tx = db.tx();
+        assert_eq!(tx.get_object(b"upload").unwrap().unwrap(), b"new bytes");
+        assert!(tx.delete_object(b"upload").unwrap());
+        assert!(!tx.delete_object(b"missing").unwrap());
+        tx.commit().unwrap();
+
+        assert!(db.snapshot().get_object(b"upload").unwrap().is_none());
+        assert_eq!(live_blobs(&db), 0);
+    }
+}
+
+#[test]
+fn object_conflict() {
+    let path = tmp_path!();
+    let db = db::open(&path).unwrap();
+
+    let mut tx1 = db.tx();
+    let mut tx2 = db.tx();
+
+    assert!(tx1.get(b"k").unwrap().is_none());
+    tx1.put_object(b"object", b"1");
+
+    tx2.insert(b"k", b"v").unwrap();
+    tx2.commit().unwrap();
+
+    // the blob of a transaction that fails to commit is removed
+    assert!(tx1.commit().is_err());
+    assert_eq!(live_blobs(&db), 0);
+}
@@ -0,0 +1,83 @@
+// common contains open_tmp macro
+mod common;
+
+fn keys<I: Iterator<Item = std::io::Result<(db::Bytes, db::Bytes)>>>(iter: I) -> Vec<Vec<u8>> {
+    iter.map(|entry| entry.unwrap().0.to_vec()).collect()
+}
+
+#[test]
+fn range_00() {
+    let db = open_tmp!();
+
+    {
+        let mut tx = db.tx();
+        for key in [&b"a"[..], b"b", b"c", b"d"] {
+            tx.insert(key, key).unwrap();
+        }
+        tx.commit().unwrap();
+    }
+
+    let mut tx = db.tx();
+
+    // uncommitted writes are merged in and shadow committed values
+    tx.insert(b"bb", b"bb").unwrap();
+    tx.insert(b"c", b"new").unwrap();
+
+    assert_eq!(
+        keys(tx.range::<&[u8], _>(..)),
+        vec![
+            b"a".to_vec(),
+            b"b".to_vec(),
+            b"bb".to_vec(),
+            b"c".to_vec(),
+            b"d".to_vec()
+        ]
+    );
+
+    let b_to_c: Vec<_> = tx
+        .range(&b"b"[..]..=&b"c"[..])
+        .collect::<std::io::Result<_>>()
+        .unwrap();
+    assert_eq!(b_to_c.len(), 3);
+    assert_eq!(&*b_to_c[2].1, b"new");
+
+    assert_eq!(
+        keys(tx.range(&b"b"[..]..&b"d"[..]).rev()),
+        vec![b"c".to_vec(), b"bb".to_vec(), b"b".to_vec()]
+    );
+
+    // iterating from both ends meets in the middle without overlap
+    let mut both_ends = tx.range::<&[u8], _>(..);
+    assert_eq!(&*both_ends.next().unwrap().unwrap().0, b"a");
+    assert_eq!(&*both_ends.next_back().unwrap().unwrap().0, b"d");
+    assert_eq!(
+        keys(both_ends),
+        vec![b"b".to_vec(), b"bb".to_vec(), b"c".to_vec()]
+    );
+}
+
+#[test]
+fn scan_prefix_00() {
+    let db = open_tmp!();
+
+    {
+        let mut tx = db.tx();
+        for key in [&b"a"[..], b"ab", b"abc", b"ac", &[b'a', 0xFF], b"b"] {
+            tx.insert(key, key).unwrap();
+        }
+        tx.commit().unwrap();
+    }
+
+    let mut tx = db.tx();
+
+    assert_eq!(
+        keys(tx.scan_prefix(b"ab")),
+        vec![b"ab".to_vec(), b"abc".to_vec()]
+    );
+    assert_eq!(keys(tx.scan_prefix(b"a")).len(), 5);
+    assert_eq!(keys(tx.scan_prefix(b"")).len(), 6);
+    assert_eq!(
+        keys(tx.scan_prefix(b"a").rev().take(1)),
+        vec![vec![b'a', 0xFF]]
+    );
+}
@@ -0,0 +1,144 @@
+// common contains tmp_path macro
+mod common;
+
+#[test]
+fn recovery_00() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        tx.insert(b"a", b"a").unwrap();
+        tx.insert(b"b", b"b").unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.tx();
+        tx.insert(b"a", b"c").unwrap();
+        tx.commit().unwrap();
+    }
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        assert_eq!(tx.get(b"a").unwrap().unwrap(), b"c");
+        assert_eq!(tx.get(b"b").unwrap().unwrap(), b"b");
+    }
+}
+
+#[test]
+fn recovery_torn_tail() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        tx.insert(b"a", b"a").unwrap();
+        tx.commit().unwrap();
+    }
+
+    // simulate a crash partway through appending a second batch
+    {
+        use std::io::Write;
+
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(path.join("log"))
+            .unwrap();
+        log.write_all(&[7; 9]).unwrap();
+    }
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        assert_eq!(tx.get(b"a").unwrap().unwrap(), b"a");
+        tx.insert(b"b", b"b").unwrap();
+        tx.commit().unwrap();
+    }
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        assert_eq!(tx.get(b"a").unwrap().unwrap(), b"a");
+        assert_eq!(tx.get(b"b").unwrap().unwrap(), b"b");
+    }
+}
+
+#[test]
+fn recovery_zeroed_tail() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        tx.insert(b"a", b"a").unwrap();
+        tx.commit().unwrap();
+    }
+
+    // the log was extended, but its pages were never written
+    {
+        use std::io::Write;
+
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(path.join("log"))
+            .unwrap();
+        log.write_all(&[0; 4096]).unwrap();
+    }
+
+    let db = db::open(&path).unwrap();
+    assert_eq!(db.tx().get(b"a").unwrap().unwrap(), b"a");
+    assert!(std::fs::metadata(path.join("log")).unwrap().len() < 4096);
+}
+
+#[test]
+fn recovery_corrupt_middle() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        for key in [b"a", b"b"] {
+            let mut tx = db.tx();
+            tx.insert(key, key).unwrap();
+            tx.commit().unwrap();
+        }
+    }
+
+    // flip a bit in the body of the first of two frames, which follows
+    // the 68-byte file header
+    let mut log = std::fs::read(path.join("log")).unwrap();
+    log[68 + 20] ^= 1;
+    std::fs::write(path.join("log"), &log).unwrap();
+
+    let error = db::open(&path).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("offset: 68"));
+
+    // the log is left as it was, rather than truncated
+    assert_eq!(std::fs::read(path.join("log")).unwrap(), log);
+}
+
+#[test]
+fn recovery_future_format_version() {
+    let path = tmp_path!();
+
+    drop(db::open(&path).unwrap());
+
+    // rewrite the format version of the log's header, which follows the
+    // 8-byte magic and is covered by the header's trailing CRC
+    let mut log = std::fs::read(path.join("log")).unwrap();
+    log[8..12].copy_from_slice(&100_u32.to_le_bytes());
+    let crc = crc32fast::hash(&log[..64]);
+    log[64..68].copy_from_slice(&crc.to_le_bytes());
+    std::fs::write(path.join("log"), &log).unwrap();
+
+    let error = db::open(&path).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+    assert!(error.to_string().contains("format version 100"));
+}
@@ -7,7 +7,7 @@ fn smoke_00() {
 
     {
         let mut tx = db.tx();
-        tx.insert(b"a", b"a");
+        tx.insert(b"a", b"a").unwrap();
         tx.commit().unwrap();
     }
 
@@ -17,3 +17,38 @@ fn smoke_00() {
         assert_eq!(read, b"a");
     }
 }
+
+#[test]
+fn remove_00() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        tx.insert(b"a", b"a").unwrap();
+        tx.insert(b"b", b"b").unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.tx();
+        assert_eq!(tx.remove(b"a").unwrap().unwrap(), b"a");
+
+        // the removal is visible to the rest of the transaction
+        assert!(tx.get(b"a").unwrap().is_none());
+        assert_eq!(tx.scan_prefix(b"").count(), 1);
+        assert!(tx.remove(b"a").unwrap().is_none());
+
+        tx.commit().unwrap();
+
+        let mut tx = db.tx();
+        assert!(tx.get(b"a").unwrap().is_none());
+    }
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        assert!(tx.get(b"a").unwrap().is_none());
+        assert_eq!(tx.get(b"b").unwrap().unwrap(), b"b");
+    }
+}
@@ -0,0 +1,83 @@
+// common contains open_tmp macro
+mod common;
+
+#[test]
+fn snapshot_00() {
+    let db = open_tmp!();
+
+    let empty = db.snapshot();
+
+    {
+        let mut tx = db.tx();
+        tx.insert(b"a", b"1").unwrap();
+        tx.insert(b"b", b"1").unwrap();
+        tx.commit().unwrap();
+    }
+
+    let before = db.snapshot();
+
+    {
+        let mut tx = db.tx();
+        tx.insert(b"a", b"2").unwrap();
+        tx.remove(b"b").unwrap();
+        tx.insert(b"c", b"2").unwrap();
+        tx.commit().unwrap();
+    }
+
+    assert!(empty.get(b"a").unwrap().is_none());
+    assert_eq!(empty.scan_prefix(b"").count(), 0);
+
+    assert_eq!(before.get(b"a").unwrap().unwrap(), b"1");
+    assert_eq!(before.get(b"b").unwrap().unwrap(), b"1");
+    assert!(before.get(b"c").unwrap().is_none());
+
+    let before_items: Vec<_> = before
+        .scan_prefix(b"")
+        .map(|entry| {
+            let (k, v) = entry.unwrap();
+            (k.to_vec(), v.to_vec())
+        })
+        .collect();
+    assert_eq!(
+        before_items,
+        vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"1".to_vec())
+        ]
+    );
+
+    let after = db.snapshot();
+    assert_eq!(after.get(b"a").unwrap().unwrap(), b"2");
+    assert!(after.get(b"b").unwrap().is_none());
+    assert_eq!(after.range(&b"b"[..]..).rev().count(), 1);
+}
+
+#[test]
+fn snapshot_scan_during_commits() {
+    let db = open_tmp!();
+
+    {
+        let mut tx = db.tx();
+        for i in 0_u8..10 {
+            tx.insert(&[i], b"old").unwrap();
+        }
+        tx.commit().unwrap();
+    }
+
+    let snapshot = db.snapshot();
+    let mut scan = snapshot.scan_prefix(b"");
+
+    for i in 0_u8..10 {
+        let (key, value) = scan.next().unwrap().unwrap();
+        assert_eq!(&*key, &[i]);
+        assert_eq!(&*value, b"old");
+
+        // a writer commits between every step of the scan
+        let mut tx = db.tx();
+        tx.remove(&[9 - i]).unwrap();
+        tx.insert(&[i, 0], b"new").unwrap();
+        tx.commit().unwrap();
+    }
+
+    assert!(scan.next().is_none());
+}
@@ -0,0 +1,223 @@
+// common contains open_tmp macro
+mod common;
+
+fn key(i: u32) -> [u8; 4] {
+    i.to_be_bytes()
+}
+
+fn keys(range: impl Iterator<Item = std::io::Result<(db::Bytes, db::Bytes)>>) -> Vec<Vec<u8>> {
+    range.map(|entry| entry.unwrap().0.to_vec()).collect()
+}
+
+#[test]
+fn table_00() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+
+        let mut tx = db.tx();
+        for i in 0..1000 {
+            tx.insert(&key(i), &key(i)).unwrap();
+        }
+        tx.commit().unwrap();
+
+        db.flush().unwrap();
+
+        // overwrite and remove keys that now live in a table, and flush
+        // again so that the newer table shadows the older one
+        let mut tx = db.tx();
+        for i in (0..1000).step_by(2) {
+            tx.remove(&key(i)).unwrap();
+        }
+        tx.insert(&key(1), b"new").unwrap();
+        tx.commit().unwrap();
+
+        db.flush().unwrap();
+
+        // and once more without flushing, so reads merge the memtable
+        let mut tx = db.tx();
+        tx.insert(&key(0), b"memtable").unwrap();
+        tx.remove(&key(3)).unwrap();
+        tx.commit().unwrap();
+    }
+
+    for reopen in [false, true] {
+        let db = db::open(&path).unwrap();
+        if reopen {
+            db.flush().unwrap();
+        }
+
+        let snapshot = db.snapshot();
+        assert_eq!(snapshot.get(&key(0)).unwrap().unwrap(), b"memtable");
+        assert_eq!(snapshot.get(&key(1)).unwrap().unwrap(), b"new");
+        assert!(snapshot.get(&key(2)).unwrap().is_none());
+        assert!(snapshot.get(&key(3)).unwrap().is_none());
+        assert_eq!(snapshot.get(&key(5)).unwrap().unwrap(), key(5));
+        assert!(snapshot.get(&key(1000)).unwrap().is_none());
+
+        let expected: Vec<Vec<u8>> = std::iter::once(0)
+            .chain((5..1000).step_by(2))
+            .chain(std::iter::once(1))
+            .map(|i| key(i).to_vec())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        assert_eq!(keys(snapshot.range::<&[u8], _>(..)), expected);
+
+        let mut reversed = expected.clone();
+        reversed.reverse();
+        assert_eq!(keys(snapshot.range::<&[u8], _>(..).rev()), reversed);
+
+        assert_eq!(
+            keys(snapshot.range(&key(2)[..]..&key(9)[..])),
+            vec![key(5).to_vec(), key(7).to_vec()]
+        );
+        assert_eq!(
+            keys(snapshot.scan_prefix(&[0, 0, 3])),
+            (769..1000)
+                .step_by(2)
+                .map(|i| key(i).to_vec())
+                .collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn table_snapshot_across_flush() {
+    let db = open_tmp!();
+
+    let mut tx = db.tx();
+    tx.insert(b"a", b"1").unwrap();
+    tx.commit().unwrap();
+
+    let before = db.snapshot();
+
+    let mut tx = db.tx();
+    tx.insert(b"a", b"2").unwrap();
+    tx.insert(b"b", b"2").unwrap();
+    tx.commit().unwrap();
+
+    // the pinned snapshot holds back what can be flushed
+    db.flush().unwrap();
+
+    assert_eq!(before.get(b"a").unwrap().unwrap(), b"1");
+    assert!(before.get(b"b").unwrap().is_none());
+
+    drop(before);
+    db.flush().unwrap();
+
+    let after = db.snapshot();
+    assert_eq!(after.get(b"a").unwrap().unwrap(), b"2");
+    assert_eq!(after.get(b"b").unwrap().unwrap(), b"2");
+}
+
+#[test]
+fn table_flush_threshold() {
+    let path = tmp_path!();
+
+    let mut config = db::Config::new(&path);
+    config.flush_threshold_bytes = 1024;
+    config.compaction_trigger_tables = usize::MAX;
+
+    {
+        let db = config.open().unwrap();
+
+        for i in 0..100 {
+            let mut tx = db.tx();
+            tx.insert(&key(i), &[0; 100]).unwrap();
+            tx.commit().unwrap();
+        }
+    }
+
+    let table_files = std::fs::read_dir(&path)
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_str().unwrap().starts_with("table.")
+        })
+        .count();
+    assert!(table_files > 1);
+
+    let db = db::open(&path).unwrap();
+    assert_eq!(db.snapshot().range::<&[u8], _>(..).count(), 100);
+}
+
+#[test]
+fn table_flush_threshold_with_pinned_snapshot() {
+    let path = tmp_path!();
+
+    let mut config = db::Config::new(&path);
+    config.flush_threshold_bytes = 1024;
+    config.compaction_trigger_tables = usize::MAX;
+
+    let db = config.open().unwrap();
+
+    let mut tx = db.tx();
+    tx.insert(b"pinned", b"").unwrap();
+    tx.commit().unwrap();
+    let snapshot = db.snapshot();
+
+    // nothing after the snapshot can be flushed, so the log isn't sealed
+    // after every commit that exceeds the threshold
+    for i in 0..50 {
+        let mut tx = db.tx();
+        tx.insert(&key(i), &[0; 100]).unwrap();
+        tx.commit().unwrap();
+    }
+
+    let log_files = std::fs::read_dir(&path)
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_str().unwrap().starts_with("log.")
+        })
+        .count();
+    assert!(log_files <= 2, "{log_files} log segments");
+
+    drop(snapshot);
+    assert_eq!(db.snapshot().range::<&[u8], _>(..).count(), 51);
+}
+
+#[test]
+fn table_corrupt_block() {
+    let path = tmp_path!();
+
+    {
+        let db = db::open(&path).unwrap();
+        let mut tx = db.tx();
+        for i in 0..1000 {
+            tx.insert(&key(i), &key(i)).unwrap();
+        }
+        tx.commit().unwrap();
+        db.flush().unwrap();
+    }
+
+    // flip a bit in the body of the first data block, which follows the
+    // 68-byte file header and the 18-byte frame header
+    let table = std::fs::read_dir(&path)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            path.file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("table.")
+        })
+        .unwrap();
+    let mut buf = std::fs::read(&table).unwrap();
+    buf[68 + 18 + 8] ^= 1;
+    std::fs::write(&table, &buf).unwrap();
+
+    // reads that reach the block fail rather than panic
+    let db = db::open(&path).unwrap();
+    let snapshot = db.snapshot();
+    let error = snapshot.get(&key(0)).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(snapshot.range::<&[u8], _>(..).next().unwrap().is_err());
+
+    let mut tx = db.tx();
+    assert!(tx.insert(&key(0), b"new").is_err());
+}
@@ -7,7 +7,7 @@ fn transactor_00() {
 
     {
         let mut tx = db.tx();
-        tx.insert(b"a", b"a");
+        tx.insert(b"a", b"a").unwrap();
         tx.commit().unwrap();
     }
 
@@ -17,3 +17,131 @@ fn transactor_00() {
         assert_eq!(read, b"a");
     }
 }
+
+fn append(
+    suffix: &'static [u8],
+) -> impl Fn(&db::TransactorContext<'_>, db::WriteBatch) -> db::TransactorResult + Send + Sync {
+    move |_context, mut batch| {
+        for value in batch.values_mut().flatten() {
+            let mut appended = value.to_vec();
+            appended.extend_from_slice(suffix);
+            *value = db::Bytes::from(appended);
+        }
+        Ok(batch)
+    }
+}
+
+#[test]
+fn transactor_registration_order() {
+    let path = tmp_path!();
+    let everything = || db::InterestFilter::Range {
+        start: std::ops::Bound::Unbounded,
+        end: std::ops::Bound::Unbounded,
+    };
+
+    {
+        let db = db::open(&path).unwrap();
+        let c = db.open_collection("c").unwrap();
+
+        db.register_transactor("first", &c, everything(), append(b"1"))
+            .unwrap();
+        db.register_transactor("second", &c, everything(), append(b"2"))
+            .unwrap();
+        assert!(
+            db.register_transactor("second", &c, everything(), append(b"2"))
+                .is_err()
+        );
+
+        // transactors only apply to their own collection
+        let mut tx = db.tx();
+        tx.insert_in(&c, b"k", b"v").unwrap();
+        tx.insert(b"k", b"v").unwrap();
+        tx.commit().unwrap();
+
+        let snapshot = db.snapshot();
+        assert_eq!(snapshot.get_in(&c, b"k").unwrap().unwrap(), b"v12");
+        assert_eq!(snapshot.get(b"k").unwrap().unwrap(), b"v");
+    }
+
+    {
+        let db = db::open(&path).unwrap();
+        let c = db.open_collection("c").unwrap();
+
+        // the persisted order wins over the order of registration
+        db.register_transactor("second", &c, everything(), append(b"2"))
+            .unwrap();
+        db.register_transactor("first", &c, everything(), append(b"1"))
+            .unwrap();
+
+        // a db with transactors can be shared between threads
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut tx = db.tx();
+                tx.insert_in(&c, b"k", b"v").unwrap();
+                tx.commit().unwrap();
+            });
+        });
+        assert_eq!(db.snapshot().get_in(&c, b"k").unwrap().unwrap(), b"v12");
+
+        // unregistering forgets the position
+        assert!(db.unregister_transactor("first").unwrap());
+        assert!(!db.unregister_transactor("first").unwrap());
+        db.register_transactor("first", &c, everything(), append(b"1"))
+            .unwrap();
+
+        let mut tx = db.tx();
+        tx.insert_in(&c, b"k", b"v").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(db.snapshot().get_in(&c, b"k").unwrap().unwrap(), b"v21");
+    }
+}
+
+#[test]
+fn transactor_rejects_commit() {
+    let db = open_tmp!();
+
+    // values must be unique, which is enforced through an index of
+    // values in a second collection
+    let users = db.open_collection("users").unwrap();
+    let emails = db.open_collection("emails").unwrap();
+
+    db.register_transactor(
+        "unique-email",
+        &users,
+        db::InterestFilter::Range {
+            start: std::ops::Bound::Unbounded,
+            end: std::ops::Bound::Unbounded,
+        },
+        move |context, batch| {
+            for (user, email) in &batch {
+                let Some(email) = email else { continue };
+
+                let owner = context.snapshot().get_in(&emails, email)?;
+                if owner.is_some_and(|owner| owner != **user) {
+                    return Err(format!("email {email:?} is taken").into());
+                }
+            }
+            Ok(batch)
+        },
+    )
+    .unwrap();
+
+    let mut tx = db.tx();
+    tx.insert_in(&users, b"alice", b"a@example.com").unwrap();
+    tx.insert_in(&emails, b"a@example.com", b"alice").unwrap();
+    tx.commit().unwrap();
+
+    let mut tx = db.tx();
+    tx.insert_in(&users, b"mallory", b"a@example.com").unwrap();
+    tx.insert(b"unrelated", b"write").unwrap();
+    let rejected = match tx.commit().unwrap_err().narrow::<db::Rejected, _>() {
+        Ok(rejected) => rejected,
+        Err(other) => panic!("expected a rejection, got {other:?}"),
+    };
+    assert_eq!(rejected.transactor, "unique-email");
+
+    // nothing from the rejected transaction was applied
+    let snapshot = db.snapshot();
+    assert!(snapshot.get_in(&users, b"mallory").unwrap().is_none());
+    assert!(snapshot.get(b"unrelated").unwrap().is_none());
+}
@@ -0,0 +1,81 @@
+// common contains open_tmp macro
+mod common;
+
+use std::ops::Bound;
+
+use db::{Bytes, InterestFilter};
+
+fn keys(event: &db::WatchEvent) -> Vec<&[u8]> {
+    event.batch.keys().map(|key| &**key).collect()
+}
+
+#[test]
+fn watch_00() {
+    let db = open_tmp!();
+
+    let mut subscriber = db.watch(InterestFilter::Range {
+        start: Bound::Included(Bytes::from(&b"user/"[..])),
+        end: Bound::Excluded(Bytes::from(&b"user0"[..])),
+    });
+
+    for key in [&b"user/1"[..], b"other", b"user/2"] {
+        let mut tx = db.tx();
+        tx.insert(key, b"v").unwrap();
+        tx.commit().unwrap();
+    }
+
+    let mut tx = db.tx();
+    tx.remove(b"user/1").unwrap();
+    tx.insert(b"other", b"v2").unwrap();
+    tx.commit().unwrap();
+
+    let first = subscriber.next().unwrap();
+    let second = subscriber.next().unwrap();
+    let third = subscriber.next().unwrap();
+
+    assert_eq!(keys(&first), vec![b"user/1"]);
+    assert_eq!(keys(&second), vec![b"user/2"]);
+    assert!(first.lsn < second.lsn && second.lsn < third.lsn);
+
+    // the whole batch of a matching commit is delivered, removals
+    // included
+    assert_eq!(keys(&third), vec![&b"other"[..], b"user/1"]);
+    assert_eq!(third.batch[&Bytes::from(&b"user/1"[..])], None);
+
+    // subscribers end once the database is dropped
+    drop(db);
+    assert!(subscriber.next().is_none());
+}
+
+#[test]
+fn watch_on_executor() {
+    let db = open_tmp!();
+    let collection = db.open_collection("c").unwrap();
+
+    let mut subscriber = db.watch_in(&collection, InterestFilter::Key(Bytes::from(&b"k"[..])));
+
+    let executor = drama::Executor::new(1);
+    let tenant_id = drama::TenantId::new("watch_on_executor");
+    let received = executor.execute(tenant_id, drama::Classification::Read, async move {
+        let mut lsns = vec![];
+        while let Some(event) = subscriber.next_event().await {
+            lsns.push(event.lsn);
+        }
+        lsns
+    });
+
+    let mut tx = db.tx();
+    tx.insert(b"k", b"default collection").unwrap();
+    tx.insert_in(&collection, b"k", b"1").unwrap();
+    tx.commit().unwrap();
+
+    let mut tx = db.tx();
+    tx.insert_in(&collection, b"k", b"2").unwrap();
+    tx.commit().unwrap();
+
+    drop(db);
+
+    let lsns = received.recv().unwrap();
+    assert_eq!(lsns.len(), 2);
+    assert!(lsns[0] < lsns[1]);
+}
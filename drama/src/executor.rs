@@ -65,12 +65,17 @@ struct ExecutorWaker {
 impl Wake for ExecutorWaker {
     fn wake(self: Arc<Self>) {
         let task_state = self.task_state.clone();
-        let priority = self
-            .worker_state
+        let worker_state = self.worker_state.clone();
+        let priority = worker_state
             .priority_for_tenant_id_and_classification(self.tenant_id, self.classification);
 
-        self.worker_state.pq.push(
-            Work::Work(Box::new(move || task_state.lock().unwrap().poll())),
+        // The task only holds a weak reference to its waker, so this one
+        // is kept alive until the task is polled, as it may be the last.
+        worker_state.pq.push(
+            Work::Work(Box::new(move || {
+                let _waker = self;
+                task_state.lock().unwrap().poll()
+            })),
             priority,
         );
     }
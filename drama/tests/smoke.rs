@@ -1,3 +1,6 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
 use komora_sync::{oneshot, ReceiveOne};
@@ -83,3 +86,44 @@ fn executor_test_02() {
         assert_eq!(res, 1);
     }
 }
+
+/// Returns pending once, waking its task by value from another thread.
+struct WakeByValue {
+    woken: bool,
+}
+
+impl Future for WakeByValue {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.woken {
+            return Poll::Ready(());
+        }
+        self.woken = true;
+
+        let waker = cx.waker().clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            waker.wake();
+        });
+
+        Poll::Pending
+    }
+}
+
+#[test]
+fn executor_test_wake_by_value() {
+    let executor = Executor::new(1);
+    let tenant_id = TenantId::new(0);
+    let classification = Classification::Compute;
+
+    // the waker consumed by `wake` may be the last one the task has
+    let recv = executor.execute(tenant_id, classification, async {
+        WakeByValue { woken: false }.await;
+        1_usize
+    });
+
+    let res: usize = recv.recv().expect("executor thread died unexpectedly");
+
+    assert_eq!(res, 1);
+}